@@ -0,0 +1,54 @@
+// Counts the heap allocations made while running each Lox script in this directory on the
+// tree-walking interpreter, to compare revisions:
+//
+//   cargo bench --bench allocations
+use rslox::Interpreter;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fs, io};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn main() {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/benches");
+    let mut scripts: Vec<_> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    scripts.sort();
+
+    for script in scripts {
+        let source = fs::read_to_string(&script).unwrap();
+        let mut interpreter = Interpreter::with_output(io::sink(), io::sink());
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let bytes = BYTES.load(Ordering::Relaxed);
+        interpreter.run(&source).unwrap();
+
+        println!(
+            "  {:<12} {:>10} allocations {:>12} bytes",
+            script.file_stem().unwrap().to_string_lossy(),
+            ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+            BYTES.load(Ordering::Relaxed) - bytes
+        );
+    }
+}
@@ -0,0 +1,42 @@
+#!/bin/sh
+# Times the Lox scripts in this directory on the tree-walking interpreter.
+#
+#   benches/run.sh                 the working tree
+#   benches/run.sh <rev>...        each revision, to compare them
+#
+# Each script prints its result and then the seconds it took, the result has to be the same
+# on every revision.
+set -eu
+
+root=$(cd "$(dirname "$0")/.." && pwd)
+backend=${BACKEND:-tree}
+
+run_scripts() {
+  binary=$1
+  for script in "$root"/benches/*.lox; do
+    output=$("$binary" --backend="$backend" "$script")
+    result=$(echo "$output" | head -n 1)
+    seconds=$(echo "$output" | tail -n 1)
+    printf '  %-12s %8.3fs  (%s)\n' "$(basename "$script" .lox)" "$seconds" "$result"
+  done
+}
+
+if [ $# -eq 0 ]; then
+  cargo build --release --quiet --manifest-path "$root/Cargo.toml"
+  echo "working tree:"
+  run_scripts "$root/target/release/rslox"
+  exit
+fi
+
+worktree=$(mktemp -d)
+trap 'git -C "$root" worktree remove --force "$worktree"' EXIT
+for revision in "$@"; do
+  git -C "$root" worktree add --quiet --force --detach "$worktree" "$revision"
+  cargo build --release --quiet --manifest-path "$worktree/Cargo.toml" \
+    --target-dir "$root/target/bench"
+  echo "$revision:"
+  run_scripts "$root/target/bench/release/rslox"
+  git -C "$root" worktree remove --force "$worktree"
+done
+trap - EXIT
+rm -rf "$worktree"
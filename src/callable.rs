@@ -0,0 +1,108 @@
+use crate::class::LoxInstance;
+use crate::environment::Environment;
+use crate::expression::{LiteralValue, Local};
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::statement::FunctionDeclaration;
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+pub trait Callable {
+    fn arity(&self) -> usize;
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LiteralValue>,
+    ) -> Result<LiteralValue, RuntimeError>;
+}
+
+pub struct LoxFunction {
+    declaration: Rc<FunctionDeclaration>,
+    // the scope the function was declared in, `None` for the global scope
+    closure: Option<Rc<RefCell<Environment>>>,
+    // initializers always return `this`, whatever their body does
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: Rc<FunctionDeclaration>,
+        closure: Option<Rc<RefCell<Environment>>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    // creates a copy of the method with `this` bound to the instance
+    pub fn bind(&self, instance: Rc<LoxInstance>) -> LoxFunction {
+        let mut environment = Environment::new(self.closure.clone());
+        environment.define(LiteralValue::Instance(instance));
+
+        LoxFunction::new(
+            Rc::clone(&self.declaration),
+            Some(Rc::new(RefCell::new(environment))),
+            self.is_initializer,
+        )
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LiteralValue>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        // the parameters take the first slots, in order
+        let mut environment = Environment::new(self.closure.clone());
+        for argument in arguments {
+            environment.define(argument);
+        }
+
+        interpreter.execute_block(&self.declaration.body, Rc::new(RefCell::new(environment)))?;
+        let return_value = interpreter.take_return_value();
+        if self.is_initializer {
+            // the closure of a bound initializer is the scope holding `this`
+            let this = Local { depth: 0, slot: 0 };
+            let closure = self.closure.as_ref().expect("Initializers are bound");
+            return Ok(closure.borrow().get_at(this));
+        }
+
+        Ok(return_value.unwrap_or(LiteralValue::Nil))
+    }
+}
+
+// functions are only equal to themselves, not to other functions with the same body
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        let same_closure = match (&self.closure, &other.closure) {
+            (Some(left), Some(right)) => Rc::ptr_eq(left, right),
+            (None, None) => true,
+            _ => false,
+        };
+        Rc::ptr_eq(&self.declaration, &other.declaration) && same_closure
+    }
+}
+
+// the closure is left out, it can (indirectly) contain the function itself
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoxFunction")
+            .field("name", &self.declaration.name.lexeme)
+            .finish()
+    }
+}
+
+impl fmt::Display for LoxFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
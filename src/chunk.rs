@@ -0,0 +1,187 @@
+use crate::token::Span;
+use std::rc::Rc;
+
+// The instructions of the bytecode backend. Each is one byte, some are followed by operands:
+// constant indices and jump offsets take two bytes (big endian), slots and counts one byte.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    // index of the constant to push
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    // slot in the current frame
+    GetLocal,
+    SetLocal,
+    // index of the name constant
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    // index into the closure's upvalues
+    GetUpvalue,
+    SetUpvalue,
+    // index of the name constant
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    // distance to jump forwards
+    Jump,
+    JumpIfFalse,
+    // distance to jump backwards
+    Loop,
+    // number of arguments
+    Call,
+    // index of the function constant, then a pair of bytes for each upvalue the function
+    // captures: whether it is a local of the enclosing function, and its slot or upvalue index
+    Closure,
+    CloseUpvalue,
+    Return,
+    // index of the name constant
+    Class,
+    Inherit,
+    Method,
+}
+
+// in the order of their byte values
+const OPCODES: [OpCode; 37] = [
+    OpCode::Constant,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetGlobal,
+    OpCode::DefineGlobal,
+    OpCode::SetGlobal,
+    OpCode::GetUpvalue,
+    OpCode::SetUpvalue,
+    OpCode::GetProperty,
+    OpCode::SetProperty,
+    OpCode::GetSuper,
+    OpCode::Equal,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Not,
+    OpCode::Negate,
+    OpCode::Print,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::Loop,
+    OpCode::Call,
+    OpCode::Closure,
+    OpCode::CloseUpvalue,
+    OpCode::Return,
+    OpCode::Class,
+    OpCode::Inherit,
+    OpCode::Method,
+];
+
+impl OpCode {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        OPCODES.get(byte as usize).copied()
+    }
+}
+
+// the values known at compile time
+#[derive(Debug, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    // the source each byte of code was compiled from, for runtime errors
+    pub spans: Vec<Span>,
+    pub constants: Vec<Constant>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: Span) {
+        self.code.push(byte);
+        self.spans.push(span);
+    }
+
+    // names are used over and over, so strings are only stored once
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        if let Constant::String(string) = &constant {
+            let existing = self.constants.iter().position(
+                |existing| matches!(existing, Constant::String(other) if other == string),
+            );
+            if let Some(index) = existing {
+                return index;
+            }
+        }
+
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn read_short(&self, offset: usize) -> usize {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]]) as usize
+    }
+}
+
+// a compiled function, the top level of a script is one without a name
+#[derive(Debug, Default, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl Function {
+    // how the function is shown by `print` and in listings
+    pub fn display(&self) -> String {
+        if self.name.is_empty() {
+            "<script>".to_string()
+        } else {
+            format!("<fn {}>", self.name)
+        }
+    }
+}
+
+#[test]
+fn test_opcodes_round_trip_through_bytes() {
+    for (byte, opcode) in OPCODES.iter().enumerate() {
+        assert_eq!(*opcode as u8, byte as u8);
+        assert_eq!(OpCode::from_byte(byte as u8), Some(*opcode));
+    }
+    assert_eq!(OpCode::from_byte(OPCODES.len() as u8), None);
+}
+
+#[test]
+fn test_string_constants_are_stored_once() {
+    let mut chunk = Chunk::default();
+
+    let first = chunk.add_constant(Constant::String(Rc::from("a")));
+    let number = chunk.add_constant(Constant::Number(1.0));
+    let second = chunk.add_constant(Constant::String(Rc::from("a")));
+
+    assert_eq!((first, number, second), (0, 1, 0));
+    assert_eq!(chunk.constants.len(), 2);
+}
@@ -0,0 +1,133 @@
+use crate::callable::{Callable, LoxFunction};
+use crate::expression::LiteralValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::token::Token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct LoxClass {
+    name: Rc<str>,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Rc<str>, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: Rc<str>,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<Rc<str>, Rc<LoxFunction>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    // methods are looked up in the class first, then up the superclass chain
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        self.methods.get(name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+// implemented on the `Rc` so that the new instance can point back to its class
+impl Callable for Rc<LoxClass> {
+    fn arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |initializer| initializer.arity())
+    }
+
+    // calling a class constructs a new instance and runs `init` on it, if there is one
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LiteralValue>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let instance = Rc::new(LoxInstance::new(Rc::clone(self)));
+
+        if let Some(initializer) = self.find_method("init") {
+            initializer
+                .bind(Rc::clone(&instance))
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(LiteralValue::Instance(instance))
+    }
+}
+
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Display for LoxClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<Rc<str>, LiteralValue>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // fields shadow methods, methods are bound to the instance they are accessed on
+    pub fn get(self: &Rc<Self>, name: &Token) -> Result<LiteralValue, RuntimeError> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        match self.class.find_method(&name.lexeme) {
+            Some(method) => Ok(LiteralValue::Function(Rc::new(
+                method.bind(Rc::clone(self)),
+            ))),
+            None => Err(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    pub fn set(&self, name: &Token, value: LiteralValue) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
+
+// instances are only equal to themselves
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// the fields are left out, they can (indirectly) contain the instance itself
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoxInstance")
+            .field("class", &self.class.name)
+            .finish()
+    }
+}
+
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
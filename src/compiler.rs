@@ -0,0 +1,778 @@
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+use crate::expression::{Binding, Expr, LiteralValue, Visitor};
+use crate::interner::intern;
+#[cfg(test)]
+use crate::parser::Parser;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Span, Token, TokenType};
+use std::fmt;
+use std::fmt::Formatter;
+use std::mem;
+use std::rc::Rc;
+
+// local slots and upvalues are addressed with a single byte
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+// a limit of the bytecode format that the source goes over
+#[derive(Debug, PartialEq)]
+pub struct CompileError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.span.line, self.message)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Initializer,
+    Method,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    // captured locals are moved off the stack when their scope ends
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueReference {
+    // whether the variable is a local of the enclosing function or one of its upvalues
+    is_local: bool,
+    index: u8,
+}
+
+// a function that is being compiled
+struct FunctionState {
+    function: Function,
+    function_type: FunctionType,
+    // in the order of their stack slots, slot 0 holds the called function or `this`
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueReference>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: &str, function_type: FunctionType) -> Self {
+        let receiver = match function_type {
+            FunctionType::Initializer | FunctionType::Method => "this",
+            FunctionType::Script | FunctionType::Function => "",
+        };
+
+        Self {
+            function: Function {
+                name: name.to_string(),
+                ..Function::default()
+            },
+            function_type,
+            locals: vec![Local {
+                name: receiver.to_string(),
+                depth: 0,
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+
+    // innermost declarations shadow outer ones
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local.name == name)
+    }
+}
+
+// Compiles a script into bytecode for the virtual machine. The syntax tree has to be free of
+// resolve errors, the compiler only checks the limits of the bytecode format. Locals live in
+// stack slots, the variables that closures capture are tracked as upvalues.
+pub struct Compiler {
+    // the function being compiled last, the script first
+    functions: Vec<FunctionState>,
+    // the source the emitted instructions are attributed to
+    span: Span,
+    errors: Vec<CompileError>,
+}
+
+impl Visitor<()> for Compiler {
+    fn visit_binary_expression(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.visit_expression(left);
+        self.visit_expression(right);
+
+        self.span = operator.span;
+        match operator.token_type {
+            TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+            TokenType::BangEqual => {
+                self.emit_op(OpCode::Equal);
+                self.emit_op(OpCode::Not);
+            }
+            TokenType::Greater => self.emit_op(OpCode::Greater),
+            TokenType::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+            TokenType::Less => self.emit_op(OpCode::Less),
+            TokenType::LessEqual => self.emit_op(OpCode::LessEqual),
+            TokenType::Plus => self.emit_op(OpCode::Add),
+            TokenType::Minus => self.emit_op(OpCode::Subtract),
+            TokenType::Star => self.emit_op(OpCode::Multiply),
+            TokenType::Slash => self.emit_op(OpCode::Divide),
+            _ => unreachable!("the parser only creates binary expressions for binary operators"),
+        }
+    }
+
+    fn visit_call_expression(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) {
+        self.visit_expression(callee);
+        for argument in arguments {
+            self.visit_expression(argument);
+        }
+
+        // the parser allows at most 255 arguments
+        self.span = paren.span;
+        self.emit_op(OpCode::Call);
+        self.emit_byte(arguments.len() as u8);
+    }
+
+    fn visit_get_expression(&mut self, object: &Expr, name: &Token) {
+        self.visit_expression(object);
+
+        self.span = name.span;
+        self.emit_name_op(OpCode::GetProperty, &name.lexeme);
+    }
+
+    fn visit_grouping_expression(&mut self, expr: &Expr) {
+        self.visit_expression(expr);
+    }
+
+    fn visit_literal_expression(&mut self, value: &LiteralValue) {
+        match value {
+            LiteralValue::Boolean(true) => self.emit_op(OpCode::True),
+            LiteralValue::Boolean(false) => self.emit_op(OpCode::False),
+            LiteralValue::Nil => self.emit_op(OpCode::Nil),
+            LiteralValue::Number(number) => {
+                self.emit_constant_op(OpCode::Constant, Constant::Number(*number))
+            }
+            LiteralValue::String(string) => {
+                self.emit_constant_op(OpCode::Constant, Constant::String(Rc::clone(string)))
+            }
+            _ => unreachable!(
+                "the parser only creates literals for numbers, strings, booleans and nil"
+            ),
+        }
+    }
+
+    // the left operand stays on the stack as the result if it decides it
+    fn visit_logical_expression(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        self.visit_expression(left);
+
+        if operator.token_type == TokenType::And {
+            let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit_op(OpCode::Pop);
+            self.visit_expression(right);
+            self.patch_jump(end_jump);
+        } else {
+            let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+            let end_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(else_jump);
+            self.emit_op(OpCode::Pop);
+            self.visit_expression(right);
+            self.patch_jump(end_jump);
+        }
+    }
+
+    fn visit_set_expression(&mut self, object: &Expr, name: &Token, value: &Expr) {
+        self.visit_expression(object);
+        self.visit_expression(value);
+
+        self.span = name.span;
+        self.emit_name_op(OpCode::SetProperty, &name.lexeme);
+    }
+
+    fn visit_super_expression(&mut self, keyword: &Token, method: &Token, _binding: &Binding) {
+        self.span = keyword.span;
+        self.named_variable("this", true);
+        self.named_variable("super", true);
+
+        self.span = method.span;
+        self.emit_name_op(OpCode::GetSuper, &method.lexeme);
+    }
+
+    fn visit_this_expression(&mut self, keyword: &Token, _binding: &Binding) {
+        self.span = keyword.span;
+        self.named_variable("this", true);
+    }
+
+    fn visit_unary_expression(&mut self, operator: &Token, expr: &Expr) {
+        self.visit_expression(expr);
+
+        self.span = operator.span;
+        match operator.token_type {
+            TokenType::Minus => self.emit_op(OpCode::Negate),
+            TokenType::Bang => self.emit_op(OpCode::Not),
+            _ => unreachable!("the parser only creates unary expressions for '-' and '!'"),
+        }
+    }
+
+    // instructions are attributed to the innermost expression they come from
+    fn visit_expression(&mut self, expr: &Expr) {
+        let enclosing_span = mem::replace(&mut self.span, expr.span());
+
+        match expr {
+            Expr::Binary(left, operator, right, _) => {
+                self.visit_binary_expression(left, operator, right)
+            }
+            Expr::Call(callee, paren, arguments, _) => {
+                self.visit_call_expression(callee, paren, arguments)
+            }
+            Expr::Get(object, name, _) => self.visit_get_expression(object, name),
+            Expr::Grouping(expr, _) => self.visit_grouping_expression(expr),
+            Expr::Literal(literal_value, _) => self.visit_literal_expression(literal_value),
+            Expr::Logical(left, operator, right, _) => {
+                self.visit_logical_expression(left, operator, right)
+            }
+            Expr::Set(object, name, value, _) => self.visit_set_expression(object, name, value),
+            Expr::Super(keyword, method, binding, _) => {
+                self.visit_super_expression(keyword, method, binding)
+            }
+            Expr::This(keyword, binding, _) => self.visit_this_expression(keyword, binding),
+            Expr::Unary(operator, expr, _) => self.visit_unary_expression(operator, expr),
+            Expr::Variable(token, binding, _) => self.visit_variable_expression(token, binding),
+            Expr::Assignment(token, expr, binding, _) => {
+                self.visit_assignment_expression(token, expr, binding)
+            }
+        }
+
+        self.span = enclosing_span;
+    }
+
+    fn visit_variable_expression(&mut self, name_token: &Token, _binding: &Binding) {
+        self.span = name_token.span;
+        self.named_variable(&name_token.lexeme, true);
+    }
+
+    fn visit_assignment_expression(
+        &mut self,
+        name_token: &Token,
+        right: &Expr,
+        _binding: &Binding,
+    ) {
+        self.visit_expression(right);
+
+        self.span = name_token.span;
+        self.named_variable(&name_token.lexeme, false);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        let enclosing_span = mem::replace(&mut self.span, statement.span());
+
+        match statement {
+            Statement::Expression(expr, _) => {
+                self.visit_expression(expr);
+                self.emit_op(OpCode::Pop);
+            }
+            Statement::Print(expr, _) => {
+                self.visit_expression(expr);
+                self.emit_op(OpCode::Print);
+            }
+            Statement::Var(token, initializer, _) => {
+                self.visit_var_declaration_statement(token, initializer)
+            }
+            Statement::Block(statements, _) => self.visit_block(statements),
+            Statement::If(condition, then_branch, else_branch, _) => {
+                self.visit_if_statement(condition, then_branch, else_branch)
+            }
+            Statement::While(condition, body, _) => self.visit_while_statement(condition, body),
+            Statement::Function(declaration) => self.visit_function_statement(declaration),
+            Statement::Return(keyword, value, _) => self.visit_return_statement(keyword, value),
+            Statement::Class(name, superclass, methods, _) => {
+                self.visit_class_statement(name, superclass, methods)
+            }
+        }
+
+        self.span = enclosing_span;
+    }
+
+    fn visit_var_declaration_statement(&mut self, token: &Token, initializer: &Option<Expr>) {
+        match initializer {
+            Some(initializer) => self.visit_expression(initializer),
+            None => self.emit_op(OpCode::Nil),
+        }
+
+        self.define_variable(token);
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) {
+        self.begin_scope();
+        for statement in statements {
+            self.visit_statement(statement);
+        }
+        self.end_scope();
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Statement,
+        else_branch: &Option<Box<Statement>>,
+    ) {
+        self.visit_expression(condition);
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.visit_statement(then_branch);
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+        if let Some(else_branch) = else_branch {
+            self.visit_statement(else_branch);
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn visit_while_statement(&mut self, condition: &Expr, body: &Statement) {
+        let loop_start = self.chunk().code.len();
+        self.visit_expression(condition);
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.visit_statement(body);
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+    }
+
+    fn visit_function_statement(&mut self, declaration: &Rc<FunctionDeclaration>) {
+        // a local function is declared before its body, so that it can call itself
+        if self.current().scope_depth > 0 {
+            self.add_local(&declaration.name.lexeme, declaration.name.span);
+            self.function(declaration, FunctionType::Function);
+        } else {
+            self.function(declaration, FunctionType::Function);
+            self.emit_name_op(OpCode::DefineGlobal, &declaration.name.lexeme);
+        }
+    }
+
+    fn visit_return_statement(&mut self, _keyword: &Token, value: &Option<Expr>) {
+        match value {
+            Some(value) => {
+                self.visit_expression(value);
+                self.emit_op(OpCode::Return);
+            }
+            None => self.emit_return(),
+        }
+    }
+
+    // a subclass's methods see the superclass as a local called `super` of an extra scope
+    // around them, the inherited methods are copied into the new class right away
+    fn visit_class_statement(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) {
+        self.emit_name_op(OpCode::Class, &name.lexeme);
+        self.define_variable(name);
+
+        if let Some(superclass) = superclass {
+            self.visit_expression(superclass);
+            self.begin_scope();
+            self.add_local("super", superclass.span());
+
+            self.named_variable(&name.lexeme, true);
+            let enclosing_span = mem::replace(&mut self.span, superclass.span());
+            self.emit_op(OpCode::Inherit);
+            self.span = enclosing_span;
+        }
+
+        self.named_variable(&name.lexeme, true);
+        for method in methods {
+            let function_type = if &*method.name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.function(method, function_type);
+            self.emit_name_op(OpCode::Method, &method.name.lexeme);
+        }
+        self.emit_op(OpCode::Pop);
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            functions: vec![FunctionState::new("", FunctionType::Script)],
+            span: Span::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    // the script returns the value of a final expression statement, and nil otherwise
+    pub fn compile(mut self, statements: &[Statement]) -> Result<Rc<Function>, Vec<CompileError>> {
+        match statements.split_last() {
+            Some((Statement::Expression(expr, span), rest)) => {
+                for statement in rest {
+                    self.visit_statement(statement);
+                }
+                self.span = *span;
+                self.visit_expression(expr);
+                self.emit_op(OpCode::Return);
+            }
+            _ => {
+                for statement in statements {
+                    self.visit_statement(statement);
+                }
+                // the implicit return belongs to the end of the script
+                self.span = statements.last().map_or(self.span, Statement::span);
+                self.emit_return();
+            }
+        }
+
+        let script = self.functions.pop().expect("The script is always compiled");
+        if self.errors.is_empty() {
+            Ok(Rc::new(script.function))
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    // compiles the function and emits the instruction that creates a closure for it
+    fn function(&mut self, declaration: &FunctionDeclaration, function_type: FunctionType) {
+        self.functions
+            .push(FunctionState::new(&declaration.name.lexeme, function_type));
+        self.current().function.arity = declaration.params.len();
+
+        // parameters and body share the function's scope, which `Return` discards as a whole
+        self.begin_scope();
+        for param in &declaration.params {
+            self.add_local(&param.lexeme, param.span);
+        }
+        for statement in &declaration.body {
+            self.visit_statement(statement);
+        }
+        self.emit_return();
+
+        let state = self.functions.pop().expect("The function was pushed above");
+        let mut function = state.function;
+        function.upvalue_count = state.upvalues.len();
+
+        self.emit_constant_op(OpCode::Closure, Constant::Function(Rc::new(function)));
+        for upvalue in state.upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    // the value to store is on top of the stack, where it becomes the local's slot
+    fn define_variable(&mut self, name: &Token) {
+        if self.current().scope_depth > 0 {
+            self.add_local(&name.lexeme, name.span);
+        } else {
+            self.emit_name_op(OpCode::DefineGlobal, &name.lexeme);
+        }
+    }
+
+    fn add_local(&mut self, name: &str, span: Span) {
+        if self.current().locals.len() == MAX_LOCALS {
+            self.error_at(span, "Too many local variables in function.");
+            return;
+        }
+
+        let state = self.current();
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name: name.to_string(),
+            depth,
+            is_captured: false,
+        });
+    }
+
+    // emits the instruction that reads (or assigns) the variable, wherever it lives
+    fn named_variable(&mut self, name: &str, read: bool) {
+        let function = self.functions.len() - 1;
+
+        if let Some(slot) = self.functions[function].resolve_local(name) {
+            self.emit_op(if read {
+                OpCode::GetLocal
+            } else {
+                OpCode::SetLocal
+            });
+            self.emit_byte(slot as u8);
+        } else if let Some(index) = self.resolve_upvalue(function, name) {
+            self.emit_op(if read {
+                OpCode::GetUpvalue
+            } else {
+                OpCode::SetUpvalue
+            });
+            self.emit_byte(index);
+        } else {
+            let op = if read {
+                OpCode::GetGlobal
+            } else {
+                OpCode::SetGlobal
+            };
+            self.emit_name_op(op, name);
+        }
+    }
+
+    // looks for the variable in the enclosing functions, every function in between captures it
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Option<u8> {
+        let enclosing = function.checked_sub(1)?;
+
+        if let Some(slot) = self.functions[enclosing].resolve_local(name) {
+            self.functions[enclosing].locals[slot].is_captured = true;
+            let upvalue = UpvalueReference {
+                is_local: true,
+                index: slot as u8,
+            };
+            return Some(self.add_upvalue(function, upvalue));
+        }
+
+        let index = self.resolve_upvalue(enclosing, name)?;
+        let upvalue = UpvalueReference {
+            is_local: false,
+            index,
+        };
+        Some(self.add_upvalue(function, upvalue))
+    }
+
+    fn add_upvalue(&mut self, function: usize, upvalue: UpvalueReference) -> u8 {
+        let upvalues = &self.functions[function].upvalues;
+        if let Some(index) = upvalues.iter().position(|existing| *existing == upvalue) {
+            return index as u8;
+        }
+        if upvalues.len() == MAX_UPVALUES {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+
+        let upvalues = &mut self.functions[function].upvalues;
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    // the scope's locals are popped, the captured ones are moved into their upvalues
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+
+        loop {
+            let state = self.current();
+            let is_captured = match state.locals.last() {
+                Some(local) if local.depth > state.scope_depth => local.is_captured,
+                _ => break,
+            };
+            state.locals.pop();
+
+            self.emit_op(if is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("The script is always compiled")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let span = self.span;
+        self.chunk().write(byte, span);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_short(&mut self, value: u16) {
+        for byte in value.to_be_bytes() {
+            self.emit_byte(byte);
+        }
+    }
+
+    fn emit_constant_op(&mut self, op: OpCode, constant: Constant) {
+        let index = self.chunk().add_constant(constant);
+        let index = u16::try_from(index).unwrap_or_else(|_| {
+            self.error("Too many constants in one chunk.");
+            0
+        });
+
+        self.emit_op(op);
+        self.emit_short(index);
+    }
+
+    fn emit_name_op(&mut self, op: OpCode, name: &str) {
+        self.emit_constant_op(op, Constant::String(intern(name)));
+    }
+
+    // what an empty `return;` does, initializers return `this`
+    fn emit_return(&mut self) {
+        if self.current().function_type == FunctionType::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    // emits a jump with a placeholder distance, to be filled in by `patch_jump`
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_short(u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    // makes the jump at `offset` land on the next instruction
+    fn patch_jump(&mut self, offset: usize) {
+        let distance = self.chunk().code.len() - offset - 2;
+        let distance = u16::try_from(distance).unwrap_or_else(|_| {
+            self.error("Too much code to jump over.");
+            0
+        });
+
+        let [high, low] = distance.to_be_bytes();
+        let code = &mut self.chunk().code;
+        code[offset] = high;
+        code[offset + 1] = low;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+
+        // the distance counts the operand of the `Loop` itself too
+        let distance = self.chunk().code.len() - loop_start + 2;
+        let distance = u16::try_from(distance).unwrap_or_else(|_| {
+            self.error("Loop body too large.");
+            0
+        });
+        self.emit_short(distance);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.error_at(self.span, message);
+    }
+
+    fn error_at(&mut self, span: Span, message: &str) {
+        self.errors.push(CompileError {
+            span,
+            message: message.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+fn compile_source(source: &str) -> Result<Rc<Function>, Vec<CompileError>> {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+    Compiler::new().compile(&statements)
+}
+
+#[test]
+fn test_compile_expression_statement() {
+    let script = compile_source("print 1 + 2;").expect("Compiling failed");
+
+    assert_eq!(
+        script.chunk.code,
+        vec![
+            OpCode::Constant as u8,
+            0,
+            0,
+            OpCode::Constant as u8,
+            0,
+            1,
+            OpCode::Add as u8,
+            OpCode::Print as u8,
+            OpCode::Nil as u8,
+            OpCode::Return as u8,
+        ]
+    );
+    assert_eq!(
+        script.chunk.constants,
+        vec![Constant::Number(1.0), Constant::Number(2.0)]
+    );
+}
+
+#[test]
+fn test_final_expression_is_returned() {
+    let script = compile_source("1;").expect("Compiling failed");
+
+    assert_eq!(
+        script.chunk.code,
+        vec![OpCode::Constant as u8, 0, 0, OpCode::Return as u8]
+    );
+}
+
+#[test]
+fn test_captured_locals_become_upvalues() {
+    let script = compile_source(
+        "{
+            var a = 1;
+            fun f() { return a; }
+        }",
+    )
+    .expect("Compiling failed");
+
+    let Some(Constant::Function(f)) = script.chunk.constants.get(1) else {
+        panic!("f was not compiled");
+    };
+    assert_eq!(f.upvalue_count, 1);
+    assert_eq!(
+        f.chunk.code,
+        vec![
+            OpCode::GetUpvalue as u8,
+            0,
+            OpCode::Return as u8,
+            OpCode::Nil as u8,
+            OpCode::Return as u8
+        ]
+    );
+
+    // the closure captures slot 1 of the script, which is closed when the block ends
+    let code = &script.chunk.code;
+    assert_eq!(&code[3..8], &[OpCode::Closure as u8, 0, 1, 1, 1]);
+    assert_eq!(
+        &code[8..10],
+        &[OpCode::Pop as u8, OpCode::CloseUpvalue as u8]
+    );
+}
+
+#[test]
+fn test_too_many_locals() {
+    let declarations: String = (0..MAX_LOCALS).map(|i| format!("var a{i};")).collect();
+    let errors = compile_source(&format!("{{{declarations}}}")).expect_err("Compiling succeeded");
+
+    assert_eq!(
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>(),
+        vec!["[line 1] Error: Too many local variables in function."]
+    );
+}
@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs, io};
+
+// what a test script expects from running it, read from its comments in the format of the
+// Crafting Interpreters test suite:
+//
+// print 1 + 2; // expect: 3
+// print -nil; // expect runtime error: Operand must be a number.
+// var = 1; // Error at '=': Expect variable name.
+// // [line 3] Error at end: Expect '}' after block.
+//
+// `[java line N]` errors are expected as well, `[c line N]` ones only apply to clox
+#[derive(Debug, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    // static errors, as printed on the first line of their diagnostics
+    pub errors: Vec<String>,
+    pub runtime_error: Option<String>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Expectations {
+            output: Vec::new(),
+            errors: Vec::new(),
+            runtime_error: None,
+        };
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+
+            if let Some(output) = after(line, "// expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = after(line, "// expect runtime error: ") {
+                expectations.runtime_error = Some(format!("[line {line_number}] Error: {message}"));
+            } else if let Some(error) =
+                after(line, "// [line ").or_else(|| after(line, "// [java line "))
+            {
+                expectations.errors.push(format!("[line {error}"));
+            } else if let Some(error) = after(line, "// Error") {
+                expectations
+                    .errors
+                    .push(format!("[line {line_number}] Error{error}"));
+            }
+        }
+
+        expectations
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+
+    // the differences between what was expected and what happened, empty if the test passed
+    pub fn check(&self, stdout: &str, stderr: &str, exit_code: Option<i32>) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        let output: Vec<&str> = stdout.lines().collect();
+        if output != self.output {
+            failures.push(format!(
+                "expected output {:?}, got {:?}",
+                self.output, output
+            ));
+        }
+
+        // only the first line of a diagnostic is compared, the rest shows the source
+        let errors: Vec<&str> = stderr
+            .lines()
+            .filter(|line| line.starts_with("[line "))
+            .collect();
+        let expected_errors: Vec<&str> = match &self.runtime_error {
+            Some(runtime_error) if self.errors.is_empty() => vec![runtime_error],
+            _ => self.errors.iter().map(String::as_str).collect(),
+        };
+        if errors != expected_errors {
+            failures.push(format!(
+                "expected errors {expected_errors:?}, got {errors:?}"
+            ));
+        }
+
+        if exit_code != Some(self.exit_code()) {
+            failures.push(format!(
+                "expected exit code {}, got {}",
+                self.exit_code(),
+                exit_code.map_or("none".to_string(), |code| code.to_string())
+            ));
+        }
+
+        failures
+    }
+}
+
+// runs every `.lox` script under `directory` with this executable and `arguments`, prints the
+// failures and a summary, and tells whether every test passed
+pub fn run_suite(directory: &Path, arguments: &[&str]) -> io::Result<bool> {
+    let executable = env::current_exe()?;
+    let mut scripts = Vec::new();
+    collect_scripts(directory, &mut scripts)?;
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for script in &scripts {
+        let expectations = Expectations::parse(&fs::read_to_string(script)?);
+        let result = Command::new(&executable)
+            .args(arguments)
+            .arg(script)
+            .output()?;
+
+        let failures = expectations.check(
+            &String::from_utf8_lossy(&result.stdout),
+            &String::from_utf8_lossy(&result.stderr),
+            result.status.code(),
+        );
+        if failures.is_empty() {
+            passed += 1;
+        } else {
+            failed += 1;
+            println!("FAIL {}", script.display());
+            for failure in failures {
+                println!("     {failure}");
+            }
+        }
+    }
+
+    println!("{passed} passed, {failed} failed");
+    Ok(failed == 0)
+}
+
+// the scripts in sorted order, so that runs are easy to compare
+fn collect_scripts(directory: &Path, scripts: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_scripts(&path, scripts)?;
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            scripts.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.find(marker)
+        .map(|index| line[index + marker.len()..].trim_end())
+}
+
+#[test]
+fn test_parse_expectations() {
+    let expectations = Expectations::parse(
+        "print 1; // expect: 1
+var = 2; // Error at '=': Expect variable name.
+// [line 4] Error at end: Expect expression.
+// [java line 5] Error: Unexpected character.
+// [c line 6] Error: Unexpected character.
+print -nil; // expect runtime error: Operand must be a number.",
+    );
+
+    assert_eq!(
+        expectations,
+        Expectations {
+            output: vec!["1".to_string()],
+            errors: vec![
+                "[line 2] Error at '=': Expect variable name.".to_string(),
+                "[line 4] Error at end: Expect expression.".to_string(),
+                "[line 5] Error: Unexpected character.".to_string(),
+            ],
+            runtime_error: Some("[line 6] Error: Operand must be a number.".to_string()),
+        }
+    );
+    assert_eq!(expectations.exit_code(), 65);
+}
+
+#[test]
+fn test_check_runtime_error() {
+    let expectations = Expectations::parse(
+        "print 1; // expect: 1
+print -nil; // expect runtime error: Operand must be a number.",
+    );
+
+    assert_eq!(
+        expectations.check(
+            "1\n",
+            "[line 2] Error: Operand must be a number.\n --> test.lox:2:7\n",
+            Some(70)
+        ),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        expectations.check("1\n2\n", "", Some(0)),
+        vec![
+            "expected output [\"1\"], got [\"1\", \"2\"]",
+            "expected errors [\"[line 2] Error: Operand must be a number.\"], got []",
+            "expected exit code 70, got 0",
+        ]
+    );
+}
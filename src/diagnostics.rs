@@ -0,0 +1,258 @@
+use crate::compiler::CompileError;
+use crate::interpreter::RuntimeError;
+use crate::parser::ParseError;
+#[cfg(test)]
+use crate::parser::Parser;
+use crate::resolver::ResolveError;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use crate::scanner::TokenizerError;
+use crate::token::{Span, TokenType};
+use colored::{ColoredString, Colorize};
+
+// an error ready to be shown to the user, pointing at the part of the source it is about
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    // without a span only the message and the notes are shown
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str, span: Option<Span>) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_help(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    // renders the message, the location, the offending source line with the span underlined
+    // and the notes, like this:
+    //
+    // [line 1] Error at 'print': Expect ';' after value.
+    //  --> script.lox:1:9
+    //   |
+    // 1 | print 1 print 2;
+    //   |         ^^^^^
+    pub fn render(&self, file_name: &str, source: &str, color: bool) -> String {
+        let mut output = paint(&self.message, color, |text| text.red().bold()).to_string();
+
+        if let Some((span, line)) = self.span.and_then(|span| snippet(span, source)) {
+            let gutter = " ".repeat(span.line.to_string().len());
+            let bar = paint("|", color, |text| text.blue().bold());
+
+            output.push_str(&format!(
+                "\n{gutter}{} {file_name}:{}:{}",
+                paint("-->", color, |text| text.blue().bold()),
+                span.line,
+                span.column,
+            ));
+            output.push_str(&format!("\n{gutter} {bar}"));
+            output.push_str(&format!(
+                "\n{} {bar} {line}",
+                paint(&span.line.to_string(), color, |text| text.blue().bold())
+            ));
+
+            // only the part of the span on its first line is underlined, at least one caret
+            // is shown so that spans at the end of the file are visible too
+            let prefix = line.chars().take(span.column as usize - 1).count();
+            let length = source[span.start..span.end]
+                .chars()
+                .take_while(|&c| c != '\n')
+                .count()
+                .max(1);
+            output.push_str(&format!(
+                "\n{gutter} {bar} {}{}",
+                " ".repeat(prefix),
+                paint(&"^".repeat(length), color, |text| text.red().bold())
+            ));
+
+            for note in &self.notes {
+                output.push_str(&format!(
+                    "\n{gutter} {} {}: {note}",
+                    paint("=", color, |text| text.blue().bold()),
+                    paint("help", color, |text| text.bold()),
+                ));
+            }
+        } else {
+            for note in &self.notes {
+                output.push_str(&format!(
+                    "\n{}: {note}",
+                    paint("help", color, |text| text.bold())
+                ));
+            }
+        }
+
+        output
+    }
+}
+
+impl From<&TokenizerError> for Diagnostic {
+    fn from(error: &TokenizerError) -> Self {
+        let diagnostic = Diagnostic::new(&error.to_string(), Some(error.span()));
+        match error {
+            TokenizerError::UnexpectedCharacter { character, .. } => {
+                diagnostic.with_help(&format!("'{character}' is not used in Lox"))
+            }
+            TokenizerError::MalformedNumber { .. } => {
+                diagnostic.with_help("names can't start with a digit")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let diagnostic = Diagnostic::new(&error.to_string(), Some(error.token.span));
+        match error.token.token_type {
+            TokenType::Eof => diagnostic.with_help("the source ended before this was complete"),
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        Diagnostic::new(&error.to_string(), Some(error.token.span))
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        Diagnostic::new(&error.to_string(), Some(error.span))
+    }
+}
+
+// errors from native functions, and the ones `RuntimeError::within` took the span from,
+// have no line to point at
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        let span = error.token.span;
+        Diagnostic::new(&error.to_string(), (span.line != 0).then_some(span))
+    }
+}
+
+// `style` is only applied when `color` is set, `colored` itself is not asked
+pub(crate) fn paint(text: &str, color: bool, style: fn(&str) -> ColoredString) -> String {
+    if color {
+        style(text).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+// the span, checked to lie within the source, together with the line it starts on
+fn snippet(span: Span, source: &str) -> Option<(Span, &str)> {
+    source.get(span.start..span.end)?;
+
+    let line_start = source[..span.start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map_or(source.len(), |index| span.start + index);
+
+    Some((span, source[line_start..line_end].trim_end_matches('\r')))
+}
+
+#[cfg(test)]
+fn render_source(source: &str) -> Vec<String> {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    match Parser::init(&tokens).parse() {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .iter()
+            .map(|error| Diagnostic::from(error).render("test.lox", source, false))
+            .collect(),
+    }
+}
+
+#[test]
+fn test_render_underlines_the_offending_token() {
+    assert_eq!(
+        render_source("var a = 1;\nprint a print;"),
+        vec![[
+            "[line 2] Error at 'print': Expect ';' after value.",
+            " --> test.lox:2:9",
+            "  |",
+            "2 | print a print;",
+            "  |         ^^^^^",
+        ]
+        .join("\n")]
+    );
+}
+
+#[test]
+fn test_render_error_at_end_with_help() {
+    assert_eq!(
+        render_source("print (1 +"),
+        vec![[
+            "[line 1] Error at end: Expect expression.",
+            " --> test.lox:1:11",
+            "  |",
+            "1 | print (1 +",
+            "  |           ^",
+            "  = help: the source ended before this was complete",
+        ]
+        .join("\n")]
+    );
+}
+
+#[test]
+fn test_render_without_span() {
+    let diagnostic = Diagnostic::new("Something went wrong.", None).with_help("try again");
+
+    assert_eq!(
+        diagnostic.render("test.lox", "", false),
+        "Something went wrong.\nhelp: try again"
+    );
+}
+
+#[test]
+fn test_render_ignores_spans_outside_of_the_source() {
+    let diagnostic = Diagnostic::new("Out of range.", Some(Span::new(10, 12, 1, 11)));
+
+    assert_eq!(
+        diagnostic.render("test.lox", "print 1;", false),
+        "Out of range."
+    );
+}
+
+#[test]
+fn test_render_with_color() {
+    colored::control::set_override(true);
+    let rendered =
+        Diagnostic::new("Oops.", Some(Span::new(0, 5, 1, 1))).render("test.lox", "print", true);
+
+    assert!(rendered.contains(&"^^^^^".red().bold().to_string()));
+}
+
+#[test]
+fn test_render_lexical_error() {
+    let (_, errors) = Scanner::init("var a = 1 # 2;").scan_tokens();
+    let rendered: Vec<String> = errors
+        .iter()
+        .map(|error| Diagnostic::from(error).render("test.lox", "var a = 1 # 2;", false))
+        .collect();
+
+    assert_eq!(
+        rendered,
+        vec![[
+            "[line 1] Error: Unexpected character.",
+            " --> test.lox:1:11",
+            "  |",
+            "1 | var a = 1 # 2;",
+            "  |           ^",
+            "  = help: '#' is not used in Lox",
+        ]
+        .join("\n")]
+    );
+}
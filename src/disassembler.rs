@@ -0,0 +1,231 @@
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+#[cfg(test)]
+use crate::compiler::Compiler;
+#[cfg(test)]
+use crate::parser::Parser;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use std::fmt::Write;
+
+// A listing of the bytecode of the function, followed by those of the functions it contains:
+//
+// == <script> ==
+// 0000    1 CONSTANT            0 '1'
+// 0003    | PRINT
+//
+// The columns are the offset, the source line (`|` when it is the same as the previous
+// instruction's), the instruction and its operands.
+pub fn disassemble(function: &Function) -> String {
+    let mut listing = String::new();
+    disassemble_function(function, &mut listing);
+    listing
+}
+
+fn disassemble_function(function: &Function, listing: &mut String) {
+    let chunk = &function.chunk;
+    let _ = writeln!(listing, "== {} ==", function.display());
+
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(chunk, offset, listing);
+    }
+
+    for constant in &chunk.constants {
+        if let Constant::Function(function) = constant {
+            listing.push('\n');
+            disassemble_function(function, listing);
+        }
+    }
+}
+
+// writes the instruction at `offset` on a line of its own and gives the offset of the next one
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, listing: &mut String) -> usize {
+    let _ = write!(listing, "{offset:04} ");
+    let line = chunk.spans[offset].line;
+    if offset > 0 && chunk.spans[offset - 1].line == line {
+        listing.push_str("   | ");
+    } else {
+        let _ = write!(listing, "{line:4} ");
+    }
+
+    let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+        let _ = writeln!(listing, "UNKNOWN {}", chunk.code[offset]);
+        return offset + 1;
+    };
+    let name = instruction_name(op);
+
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let index = chunk.read_short(offset + 1);
+            let _ = writeln!(
+                listing,
+                "{name:<16} {index:4} '{}'",
+                constant(&chunk.constants[index])
+            );
+            offset + 3
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let _ = writeln!(listing, "{name:<16} {:4}", chunk.code[offset + 1]);
+            offset + 2
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let distance = chunk.read_short(offset + 1);
+            let target = if op == OpCode::Loop {
+                offset + 3 - distance
+            } else {
+                offset + 3 + distance
+            };
+            let _ = writeln!(listing, "{name:<16} {offset:4} -> {target}");
+            offset + 3
+        }
+        OpCode::Closure => {
+            let index = chunk.read_short(offset + 1);
+            let Constant::Function(function) = &chunk.constants[index] else {
+                let _ = writeln!(listing, "{name:<16} {index:4} (not a function)");
+                return offset + 3;
+            };
+            let _ = writeln!(listing, "{name:<16} {index:4} {}", function.display());
+
+            let mut offset = offset + 3;
+            for _ in 0..function.upvalue_count {
+                let kind = if chunk.code[offset] == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                let _ = writeln!(
+                    listing,
+                    "{offset:04}    |                     {kind} {}",
+                    chunk.code[offset + 1]
+                );
+                offset += 2;
+            }
+            offset
+        }
+        _ => {
+            let _ = writeln!(listing, "{name}");
+            offset + 1
+        }
+    }
+}
+
+fn instruction_name(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Constant => "CONSTANT",
+        OpCode::Nil => "NIL",
+        OpCode::True => "TRUE",
+        OpCode::False => "FALSE",
+        OpCode::Pop => "POP",
+        OpCode::GetLocal => "GET_LOCAL",
+        OpCode::SetLocal => "SET_LOCAL",
+        OpCode::GetGlobal => "GET_GLOBAL",
+        OpCode::DefineGlobal => "DEFINE_GLOBAL",
+        OpCode::SetGlobal => "SET_GLOBAL",
+        OpCode::GetUpvalue => "GET_UPVALUE",
+        OpCode::SetUpvalue => "SET_UPVALUE",
+        OpCode::GetProperty => "GET_PROPERTY",
+        OpCode::SetProperty => "SET_PROPERTY",
+        OpCode::GetSuper => "GET_SUPER",
+        OpCode::Equal => "EQUAL",
+        OpCode::Greater => "GREATER",
+        OpCode::GreaterEqual => "GREATER_EQUAL",
+        OpCode::Less => "LESS",
+        OpCode::LessEqual => "LESS_EQUAL",
+        OpCode::Add => "ADD",
+        OpCode::Subtract => "SUBTRACT",
+        OpCode::Multiply => "MULTIPLY",
+        OpCode::Divide => "DIVIDE",
+        OpCode::Not => "NOT",
+        OpCode::Negate => "NEGATE",
+        OpCode::Print => "PRINT",
+        OpCode::Jump => "JUMP",
+        OpCode::JumpIfFalse => "JUMP_IF_FALSE",
+        OpCode::Loop => "LOOP",
+        OpCode::Call => "CALL",
+        OpCode::Closure => "CLOSURE",
+        OpCode::CloseUpvalue => "CLOSE_UPVALUE",
+        OpCode::Return => "RETURN",
+        OpCode::Class => "CLASS",
+        OpCode::Inherit => "INHERIT",
+        OpCode::Method => "METHOD",
+    }
+}
+
+fn constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Number(number) => number.to_string(),
+        Constant::String(string) => string.to_string(),
+        Constant::Function(function) => function.display(),
+    }
+}
+
+#[cfg(test)]
+fn disassemble_source(source: &str) -> String {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+    disassemble(
+        &Compiler::new()
+            .compile(&statements)
+            .expect("Compiling failed"),
+    )
+}
+
+#[test]
+fn test_disassemble_script() {
+    assert_eq!(
+        disassemble_source("var a = 1;\nif (a) print a + 2;"),
+        "== <script> ==
+0000    1 CONSTANT            0 '1'
+0003    | DEFINE_GLOBAL       1 'a'
+0006    2 GET_GLOBAL          1 'a'
+0009    | JUMP_IF_FALSE       9 -> 24
+0012    | POP
+0013    | GET_GLOBAL          1 'a'
+0016    | CONSTANT            2 '2'
+0019    | ADD
+0020    | PRINT
+0021    | JUMP               21 -> 25
+0024    | POP
+0025    | NIL
+0026    | RETURN
+"
+    );
+}
+
+#[test]
+fn test_disassemble_nested_functions() {
+    assert_eq!(
+        disassemble_source("fun outer() {\n  var x = 1;\n  fun inner() { return x; }\n}"),
+        "== <script> ==
+0000    1 CLOSURE             0 <fn outer>
+0003    | DEFINE_GLOBAL       1 'outer'
+0006    | NIL
+0007    | RETURN
+
+== <fn outer> ==
+0000    2 CONSTANT            0 '1'
+0003    3 CLOSURE             1 <fn inner>
+0006    |                     local 1
+0008    1 NIL
+0009    | RETURN
+
+== <fn inner> ==
+0000    3 GET_UPVALUE         0
+0002    | RETURN
+0003    | NIL
+0004    | RETURN
+"
+    );
+}
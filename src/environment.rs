@@ -1,158 +1,123 @@
-use crate::expression::LiteralValue;
-use std::collections::HashMap;
-
-#[derive(Clone)]
+use crate::expression::{LiteralValue, Local};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// A local scope. Its variables are stored in the order they are declared in, which is the
+// slot the resolver gave them, so they are found by index instead of by name. Globals are
+// not in any scope, the interpreter keeps them by name.
+//
+// Scopes are shared: a block mutates its parent scope in place and a function keeps the
+// scope it was defined in alive as its closure.
 pub struct Environment {
-    enclosing: Option<Box<Environment>>,
-    values: HashMap<String, LiteralValue>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    values: Vec<LiteralValue>,
 }
 
 impl Environment {
-    pub fn new(enclosing: Option<Box<Environment>>) -> Self {
+    pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
         Self {
-            values: HashMap::new(),
+            values: Vec::new(),
             enclosing,
         }
     }
 
-    pub fn define(&mut self, name: &str, v: LiteralValue) {
-        self.values.insert(name.to_string(), v);
+    // stores the value in the next slot
+    pub fn define(&mut self, value: LiteralValue) {
+        self.values.push(value);
     }
 
-    pub fn assign(&mut self, name: &str, v: LiteralValue) {
-        match self.values.contains_key(name) {
-            true => {
-                self.values.insert(name.to_string(), v);
-            }
-            false => {
-                if let Some(enclosing_env) = &mut self.enclosing {
-                    enclosing_env.assign(name, v);
-                } else {
-                    panic!("Variable {} is not defined", name);
-                }
-            }
+    // reads the variable the resolver found `local.depth` scopes up the chain
+    pub fn get_at(&self, local: Local) -> LiteralValue {
+        if local.depth == 0 {
+            return match self.values.get(local.slot) {
+                Some(value) => value.clone(),
+                None => panic!("Slot {} is not defined", local.slot),
+            };
         }
-    }
 
-    pub fn get(&self, name: &str) -> LiteralValue {
-        match self.values.get(name) {
-            Some(value) => value.clone(),
-            None => {
-                if let Some(enclosed_env) = &self.enclosing {
-                    enclosed_env.get(name)
-                } else {
-                    panic!("Variable {} is not defined", name)
-                }
-            }
+        match &self.enclosing {
+            Some(enclosing_env) => enclosing_env.borrow().get_at(Local {
+                depth: local.depth - 1,
+                ..local
+            }),
+            None => panic!("Scope {} is not defined", local.depth),
         }
     }
-}
 
-#[test]
-fn test_define() {
-    let number_val = 3.14;
-    let expected_value = LiteralValue::Number(number_val);
-    let mut env = Environment::new(None);
+    pub fn assign_at(&mut self, local: Local, value: LiteralValue) {
+        if local.depth == 0 {
+            match self.values.get_mut(local.slot) {
+                Some(slot) => *slot = value,
+                None => panic!("Slot {} is not defined", local.slot),
+            }
+            return;
+        }
 
-    env.define("pi", expected_value.clone());
-    assert_eq!(env.get("pi"), expected_value);
+        match &self.enclosing {
+            Some(enclosing_env) => enclosing_env.borrow_mut().assign_at(
+                Local {
+                    depth: local.depth - 1,
+                    ..local
+                },
+                value,
+            ),
+            None => panic!("Scope {} is not defined", local.depth),
+        }
+    }
 }
 
-#[test]
-fn test_define_enclosed() {
-    let expected_value = LiteralValue::Number(3.14);
-    let mut enclosed_env = Box::new(Environment::new(None));
-    enclosed_env.define("pi", expected_value.clone());
-
-    let env = Environment::new(Some(enclosed_env));
-    assert_eq!(env.get("pi"), expected_value);
+#[cfg(test)]
+fn local(depth: usize, slot: usize) -> Local {
+    Local { depth, slot }
 }
 
 #[test]
-fn test_define_enclosed_no_value() {
-    let expected_value = LiteralValue::Number(3.14);
-    let enclosed_env = Box::new(Environment::new(None));
-
-    let mut env = Environment::new(Some(enclosed_env));
-
-    env.define("pi", expected_value.clone());
-    assert_eq!(env.get("pi"), expected_value);
+fn test_define() {
+    let mut env = Environment::new(None);
 
-    let enclosed_env = env.enclosing.unwrap();
+    env.define(LiteralValue::Number(2.5));
+    env.define(LiteralValue::Nil);
 
-    let enclosed_env_value = std::panic::catch_unwind(|| enclosed_env.get("pi"));
-    assert!(enclosed_env_value.is_err());
+    assert_eq!(env.get_at(local(0, 0)), LiteralValue::Number(2.5));
+    assert_eq!(env.get_at(local(0, 1)), LiteralValue::Nil);
 }
 
 #[test]
-fn test_define_enclosed_with_shadowing() {
-    let shadowed_value = LiteralValue::Number(3.14);
-    let mut enclosed_env = Box::new(Environment::new(None));
-
-    enclosed_env.define("pi", shadowed_value.clone());
-
-    let new_value = LiteralValue::Number(10.0);
+fn test_define_enclosed() {
+    let enclosed_env = Rc::new(RefCell::new(Environment::new(None)));
+    enclosed_env.borrow_mut().define(LiteralValue::Number(2.5));
 
     let mut env = Environment::new(Some(enclosed_env));
-    env.define("pi", new_value.clone());
-    assert_eq!(env.get("pi"), new_value);
+    env.define(LiteralValue::Number(10.0));
 
-    let enclosed_env = env.enclosing.unwrap();
-    assert_eq!(enclosed_env.get("pi"), shadowed_value);
+    assert_eq!(env.get_at(local(0, 0)), LiteralValue::Number(10.0));
+    assert_eq!(env.get_at(local(1, 0)), LiteralValue::Number(2.5));
 }
 
 #[test]
 fn test_assign() {
-    let number_val = 3.147;
-    let expected_value = LiteralValue::Number(number_val);
-
     let mut env = Environment::new(None);
 
-    env.define("pi", LiteralValue::Number(3.14));
-    env.assign("pi", expected_value.clone());
-
-    assert_eq!(env.get("pi"), expected_value);
-}
-
-#[test]
-#[should_panic]
-fn test_assign_no_value() {
-    let number_val = 3.147;
-    let expected_value = LiteralValue::Number(number_val);
-
-    let mut env = Environment::new(None);
-
-    env.define("pi", expected_value);
-    env.assign("tau", LiteralValue::Number(6.28));
-}
-
-#[test]
-fn test_assign_enclosed() {
-    let shadowed_value = LiteralValue::Number(3.14);
-    let mut enclosed_env = Box::new(Environment::new(None));
-
-    enclosed_env.define("pi", shadowed_value.clone());
-
-    let new_value = LiteralValue::Number(10.0);
-
-    let mut env = Environment::new(Some(enclosed_env));
-    env.assign("pi", new_value.clone());
+    env.define(LiteralValue::Number(2.5));
+    env.define(LiteralValue::Number(1.0));
+    env.assign_at(local(0, 1), LiteralValue::Number(2.75));
 
-    assert_eq!(env.get("pi"), new_value);
+    assert_eq!(env.get_at(local(0, 0)), LiteralValue::Number(2.5));
+    assert_eq!(env.get_at(local(0, 1)), LiteralValue::Number(2.75));
 }
 
 #[test]
-fn test_assign_enclosed_with_shadowing() {
-    let shadowed_value = LiteralValue::Number(3.14);
-    let mut enclosed_env = Box::new(Environment::new(None));
-
-    enclosed_env.define("pi", shadowed_value.clone());
-
-    let new_value = LiteralValue::Number(10.0);
-
-    let mut env = Environment::new(Some(enclosed_env));
-    env.define("pi", shadowed_value.clone());
-    env.assign("pi", new_value.clone());
-
-    assert_eq!(env.get("pi"), new_value);
+fn test_assign_enclosed_is_shared() {
+    let enclosed_env = Rc::new(RefCell::new(Environment::new(None)));
+    enclosed_env.borrow_mut().define(LiteralValue::Number(2.5));
+
+    let mut env = Environment::new(Some(Rc::clone(&enclosed_env)));
+    env.define(LiteralValue::Number(10.0));
+    env.assign_at(local(1, 0), LiteralValue::Number(5.0));
+
+    assert_eq!(env.get_at(local(0, 0)), LiteralValue::Number(10.0));
+    assert_eq!(
+        enclosed_env.borrow().get_at(local(0, 0)),
+        LiteralValue::Number(5.0)
+    );
 }
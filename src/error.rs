@@ -0,0 +1,78 @@
+use crate::compiler::CompileError;
+use crate::diagnostics::Diagnostic;
+use crate::interpreter::RuntimeError;
+use crate::parser::ParseError;
+use crate::resolver::ResolveError;
+use crate::scanner::TokenizerError;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::io::Write;
+
+// everything that can go wrong when running source code
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    // the source could not be scanned or parsed, nothing was run; the tokens are parsed
+    // even after lexical errors, so both kinds can be there at once
+    Syntax(Vec<TokenizerError>, Vec<ParseError>),
+    // the source parsed but misuses variables, `this` or `return`, nothing was run
+    Resolve(Vec<ResolveError>),
+    // the program goes over a limit of the bytecode format, nothing was run
+    Compile(Vec<CompileError>),
+    // execution stopped here, whatever ran before it stays done
+    Runtime(RuntimeError),
+}
+
+impl Error {
+    pub fn is_runtime(&self) -> bool {
+        matches!(self, Error::Runtime(_))
+    }
+
+    // the errors ready to be rendered against the source that was run
+    pub(crate) fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Error::Syntax(lexical_errors, parse_errors) => lexical_errors
+                .iter()
+                .map(Diagnostic::from)
+                .chain(parse_errors.iter().map(Diagnostic::from))
+                .collect(),
+            Error::Resolve(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Compile(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Runtime(error) => vec![Diagnostic::from(error)],
+        }
+    }
+
+    // writes the rendered diagnostics to `errors`
+    pub fn report(
+        &self,
+        errors: &mut impl Write,
+        file_name: &str,
+        source: &str,
+        color: bool,
+    ) -> io::Result<()> {
+        for diagnostic in self.diagnostics() {
+            writeln!(errors, "{}", diagnostic.render(file_name, source, color))?;
+        }
+        errors.flush()
+    }
+}
+
+// one error per line, in the same format as the reference implementation
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = match self {
+            Error::Syntax(lexical_errors, parse_errors) => lexical_errors
+                .iter()
+                .map(ToString::to_string)
+                .chain(parse_errors.iter().map(ToString::to_string))
+                .collect(),
+            Error::Resolve(errors) => errors.iter().map(ToString::to_string).collect(),
+            Error::Compile(errors) => errors.iter().map(ToString::to_string).collect(),
+            Error::Runtime(error) => vec![error.to_string()],
+        };
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for Error {}
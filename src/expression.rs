@@ -1,57 +1,254 @@
-use crate::statement::Statement;
-use crate::token::Token;
+use crate::callable::LoxFunction;
+use crate::class::{LoxClass, LoxInstance};
+use crate::diagnostics::paint;
+use crate::native::NativeFunction;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Span, Token};
 use colored::Colorize;
+use std::cell::Cell;
 use std::fmt;
 use std::fmt::Formatter;
+use std::rc::Rc;
 
+// the derived `PartialEq` is Lox equality: values of different types are never equal,
+// numbers follow IEEE 754 (so NaN != NaN) and objects are only equal to themselves
 #[derive(Debug, PartialEq, Clone)]
 pub enum LiteralValue {
     Boolean(bool),
     Nil,
     Number(f64),
-    String(String),
+    String(Rc<str>),
+    Function(Rc<LoxFunction>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
 }
 
+// the way `print` shows a value: strings without quotes and nothing coloured
 impl fmt::Display for LiteralValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            LiteralValue::Boolean(value) => {
-                write!(f, "{}", value.to_string().blue())
-            }
-            LiteralValue::Nil => {
-                write!(f, "{}", "nil".red())
-            }
-            LiteralValue::Number(value) => {
-                write!(f, "{}", value.to_string().yellow())
-            }
-            LiteralValue::String(value) => {
-                write!(f, "\"{}\"", value.to_string().green())
-            }
+            LiteralValue::Boolean(value) => write!(f, "{value}"),
+            LiteralValue::Nil => write!(f, "nil"),
+            LiteralValue::Number(value) => write!(f, "{value}"),
+            LiteralValue::String(value) => write!(f, "{value}"),
+            LiteralValue::Function(function) => write!(f, "{function}"),
+            LiteralValue::NativeFunction(function) => write!(f, "{function}"),
+            LiteralValue::Class(class) => write!(f, "{class}"),
+            LiteralValue::Instance(instance) => write!(f, "{instance}"),
         }
     }
 }
 
+impl LiteralValue {
+    // Lox truthiness: `nil` and `false` are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, LiteralValue::Nil | LiteralValue::Boolean(false))
+    }
+
+    // the way the REPL shows a value: coloured by type if `color` is set, and with strings
+    // quoted, so that `"1"` can be told apart from `1`
+    pub fn repr(&self, color: bool) -> String {
+        let text = self.to_string();
+        match self {
+            LiteralValue::Boolean(_) => paint(&text, color, |text| text.blue()),
+            LiteralValue::Nil => paint(&text, color, |text| text.red()),
+            LiteralValue::Number(_) => paint(&text, color, |text| text.yellow()),
+            LiteralValue::String(_) => paint(&format!("\"{text}\""), color, |text| text.green()),
+            LiteralValue::Function(_)
+            | LiteralValue::NativeFunction(_)
+            | LiteralValue::Class(_)
+            | LiteralValue::Instance(_) => paint(&text, color, |text| text.cyan()),
+        }
+    }
+}
+
+// conversions for host code exchanging values with scripts
+
+impl From<f64> for LiteralValue {
+    fn from(value: f64) -> Self {
+        LiteralValue::Number(value)
+    }
+}
+
+impl From<bool> for LiteralValue {
+    fn from(value: bool) -> Self {
+        LiteralValue::Boolean(value)
+    }
+}
+
+impl From<&str> for LiteralValue {
+    fn from(value: &str) -> Self {
+        LiteralValue::String(Rc::from(value))
+    }
+}
+
+impl From<String> for LiteralValue {
+    fn from(value: String) -> Self {
+        LiteralValue::String(Rc::from(value))
+    }
+}
+
+impl From<()> for LiteralValue {
+    fn from(_: ()) -> Self {
+        LiteralValue::Nil
+    }
+}
+
+// a failed conversion gives the value back
+impl TryFrom<LiteralValue> for f64 {
+    type Error = LiteralValue;
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        match value {
+            LiteralValue::Number(number) => Ok(number),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<LiteralValue> for bool {
+    type Error = LiteralValue;
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        match value {
+            LiteralValue::Boolean(boolean) => Ok(boolean),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<LiteralValue> for String {
+    type Error = LiteralValue;
+
+    fn try_from(value: LiteralValue) -> Result<Self, Self::Error> {
+        match value {
+            LiteralValue::String(string) => Ok(string.to_string()),
+            _ => Err(value),
+        }
+    }
+}
+
+// where a local variable is stored: `depth` scopes up from where it is used, at `slot` in
+// that scope's variables
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Local {
+    pub depth: usize,
+    pub slot: usize,
+}
+
+// the local a variable refers to, filled in by the resolver before execution; `None` means
+// the variable is a global
+pub type Binding = Cell<Option<Local>>;
+
+// every variant ends with the span of the source it was parsed from
 #[derive(Debug, PartialEq)]
 pub enum Expr {
-    Assignment(Token, Box<Expr>),
-    Binary(Box<Expr>, Token, Box<Expr>),
-    Grouping(Box<Expr>),
-    Literal(LiteralValue),
-    Unary(Token, Box<Expr>),
+    Assignment(Token, Box<Expr>, Binding, Span),
+    Binary(Box<Expr>, Token, Box<Expr>, Span),
+    // callee, closing parenthesis (for error reporting) and arguments
+    Call(Box<Expr>, Token, Vec<Expr>, Span),
+    // object and property name
+    Get(Box<Expr>, Token, Span),
+    Grouping(Box<Expr>, Span),
+    Literal(LiteralValue, Span),
+    Logical(Box<Expr>, Token, Box<Expr>, Span),
+    // object, property name and the value to assign
+    Set(Box<Expr>, Token, Box<Expr>, Span),
+    // `super` keyword and method name
+    Super(Token, Token, Binding, Span),
+    This(Token, Binding, Span),
+    Unary(Token, Box<Expr>, Span),
     // for accessing the variable, not defining it!
-    Variable(Token),
+    Variable(Token, Binding, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assignment(.., span)
+            | Expr::Binary(.., span)
+            | Expr::Call(.., span)
+            | Expr::Get(.., span)
+            | Expr::Grouping(.., span)
+            | Expr::Literal(.., span)
+            | Expr::Logical(.., span)
+            | Expr::Set(.., span)
+            | Expr::Super(.., span)
+            | Expr::This(.., span)
+            | Expr::Unary(.., span)
+            | Expr::Variable(.., span) => *span,
+        }
+    }
 }
 
 pub trait Visitor<R> {
     fn visit_binary_expression(&mut self, left: &Expr, operator: &Token, right: &Expr) -> R;
+    fn visit_call_expression(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> R;
+    fn visit_get_expression(&mut self, object: &Expr, name: &Token) -> R;
     fn visit_grouping_expression(&mut self, expr: &Expr) -> R;
     fn visit_literal_expression(&mut self, value: &LiteralValue) -> R;
+    fn visit_logical_expression(&mut self, left: &Expr, operator: &Token, right: &Expr) -> R;
+    fn visit_set_expression(&mut self, object: &Expr, name: &Token, value: &Expr) -> R;
+    fn visit_super_expression(&mut self, keyword: &Token, method: &Token, binding: &Binding) -> R;
+    fn visit_this_expression(&mut self, keyword: &Token, binding: &Binding) -> R;
     fn visit_unary_expression(&mut self, operator: &Token, expr: &Expr) -> R;
     fn visit_expression(&mut self, expr: &Expr) -> R;
-    fn visit_variable_expression(&mut self, name_token: &Token) -> R;
-    fn visit_assignment_expression(&mut self, name_token: &Token, right: &Expr) -> R;
+    fn visit_variable_expression(&mut self, name_token: &Token, binding: &Binding) -> R;
+    fn visit_assignment_expression(
+        &mut self,
+        name_token: &Token,
+        right: &Expr,
+        binding: &Binding,
+    ) -> R;
 
     fn visit_statement(&mut self, statement: &Statement) -> R;
     fn visit_var_declaration_statement(&mut self, token: &Token, initializer: &Option<Expr>) -> R;
-    fn visit_block(&mut self, expressions: &Vec<Statement>) -> R;
+    fn visit_block(&mut self, expressions: &[Statement]) -> R;
+    fn visit_if_statement(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Statement,
+        else_branch: &Option<Box<Statement>>,
+    ) -> R;
+    fn visit_while_statement(&mut self, condition: &Expr, body: &Statement) -> R;
+    fn visit_function_statement(&mut self, declaration: &Rc<FunctionDeclaration>) -> R;
+    fn visit_return_statement(&mut self, keyword: &Token, value: &Option<Expr>) -> R;
+    fn visit_class_statement(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) -> R;
+}
+
+#[test]
+fn test_display_is_plain() {
+    let values = [
+        LiteralValue::Boolean(true),
+        LiteralValue::Nil,
+        LiteralValue::Number(2.5),
+        LiteralValue::Number(3.0),
+        LiteralValue::String(Rc::from("hi")),
+    ];
+
+    let displayed: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    assert_eq!(displayed, vec!["true", "nil", "2.5", "3", "hi"]);
+}
+
+#[test]
+fn test_repr_quotes_strings() {
+    assert_eq!(LiteralValue::String(Rc::from("1")).repr(false), "\"1\"");
+    assert_eq!(LiteralValue::Number(1.0).repr(false), "1");
+}
+
+#[test]
+fn test_repr_colors_by_type_when_asked() {
+    colored::control::set_override(true);
+
+    assert_eq!(
+        LiteralValue::String(Rc::from("1")).repr(true),
+        "\"1\"".green().to_string()
+    );
+    assert_eq!(LiteralValue::Nil.repr(true), "\u{1b}[31mnil\u{1b}[0m");
 }
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// Identifiers and string constants are stored once per thread and shared from then on, so
+// that copying them is a reference count and comparing the same name is comparing pointers
+// (`Rc` checks for the same allocation before comparing contents).
+//
+// Strings made at runtime, like concatenations, are not interned. The ones nothing but the
+// interner holds any more are dropped whenever the set has doubled since they were last
+// dropped, so a long running host does not keep the names of every script it ever ran.
+thread_local! {
+    static STRINGS: RefCell<Interner> = RefCell::new(Interner {
+        strings: HashSet::new(),
+        limit: MIN_LIMIT,
+    });
+}
+
+const MIN_LIMIT: usize = 1024;
+
+struct Interner {
+    strings: HashSet<Rc<str>>,
+    // the size the set can grow to before the unused strings are dropped
+    limit: usize,
+}
+
+pub fn intern(string: &str) -> Rc<str> {
+    STRINGS.with(|interner| {
+        let mut interner = interner.borrow_mut();
+        if let Some(interned) = interner.strings.get(string) {
+            return Rc::clone(interned);
+        }
+
+        if interner.strings.len() >= interner.limit {
+            interner
+                .strings
+                .retain(|interned| Rc::strong_count(interned) > 1);
+            interner.limit = (interner.strings.len() * 2).max(MIN_LIMIT);
+        }
+
+        let interned: Rc<str> = Rc::from(string);
+        interner.strings.insert(Rc::clone(&interned));
+        interned
+    })
+}
+
+#[test]
+fn test_equal_strings_share_an_allocation() {
+    let first = intern("counter");
+    let second = intern(&String::from("counter"));
+
+    assert!(Rc::ptr_eq(&first, &second));
+    assert!(!Rc::ptr_eq(&first, &intern("count")));
+    assert_eq!(&*first, "counter");
+}
+
+#[test]
+fn test_strings_no_one_holds_are_dropped() {
+    let kept = intern("kept");
+    for i in 0..10 * MIN_LIMIT {
+        intern(&format!("name{i}"));
+    }
+
+    STRINGS.with(|interner| assert!(interner.borrow().strings.len() <= 2 * MIN_LIMIT));
+    assert!(Rc::ptr_eq(&kept, &intern("kept")));
+}
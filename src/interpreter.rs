@@ -1,33 +1,130 @@
+use crate::callable::{Callable, LoxFunction};
+use crate::class::LoxClass;
 use crate::environment::Environment;
-use crate::expression::{Expr, LiteralValue, Visitor};
-use crate::statement::Statement;
-use crate::token::{Token, TokenType};
+use crate::error::Error;
+use crate::expression::{Binding, Expr, LiteralValue, Local, Visitor};
+use crate::interner::intern;
+use crate::native;
+use crate::native::NativeFunction;
+use crate::optimizer;
+#[cfg(test)]
+use crate::parser::Parser;
+#[cfg(test)]
+use crate::resolver::Resolver;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use crate::statement::{FunctionDeclaration, Statement};
+#[cfg(test)]
+use crate::test_support::SharedBuffer;
+use crate::token::{next_source_id, Span, Token, TokenType};
+use crate::vm::FRAMES_MAX;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl RuntimeError {
+    pub(crate) fn new(token: &Token, message: &str) -> Self {
+        Self {
+            token: token.clone(),
+            message: message.to_string(),
+        }
+    }
+
+    // an error raised by a native function, which does not know where it was called from;
+    // the interpreter reports it at the call
+    pub fn native(message: &str) -> Self {
+        Self {
+            token: Token {
+                lexeme: intern(""),
+                token_type: TokenType::Eof,
+                line: 0,
+                span: Span::default(),
+            },
+            message: message.to_string(),
+        }
+    }
+
+    // an error without a token, like the ones of the bytecode backend
+    pub(crate) fn at(span: Span, message: &str) -> Self {
+        Self {
+            token: Token {
+                lexeme: intern(""),
+                token_type: TokenType::Eof,
+                line: span.line,
+                span,
+            },
+            message: message.to_string(),
+        }
+    }
+
+    // lines start at 1, only errors from `native` have none
+    fn is_located(&self) -> bool {
+        self.token.line != 0
+    }
+
+    // forgets the span if it is in another source than `source_id`, like the code of a
+    // function declared by an earlier run, so that it is not shown against the wrong text
+    pub(crate) fn within(mut self, source_id: u32) -> Self {
+        if self.token.span.source != source_id {
+            self.token.span = Span::default();
+        }
+        self
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.token.line, self.message)
+    }
+}
 
 pub struct Interpreter {
-    environment: Environment,
+    // globals are looked up by name, they can be used before they are declared
+    globals: HashMap<Rc<str>, LiteralValue>,
+    // the innermost local scope, `None` at the top level
+    environment: Option<Rc<RefCell<Environment>>>,
+    // set by a `return` statement while it unwinds to the enclosing function call
+    return_value: Option<LiteralValue>,
+    // where `print` writes to
+    output: Box<dyn Write>,
+    // where errors are reported to
+    errors: Box<dyn Write>,
+    // whether `run` passes the statements through the optimizer first
+    optimize: bool,
+    // how many calls are running, bounded like the frames of the bytecode backend
+    depth: usize,
 }
 
-impl Visitor<LiteralValue> for Interpreter {
+impl Visitor<Result<LiteralValue, RuntimeError>> for Interpreter {
     fn visit_binary_expression(
         &mut self,
         left: &Expr,
         operator: &Token,
         right: &Expr,
-    ) -> LiteralValue {
-        let left_right = (self.visit_expression(left), self.visit_expression(right));
-
-        match left_right {
-            (LiteralValue::Boolean(left), LiteralValue::Boolean(right)) => {
-                match operator.token_type {
-                    TokenType::EqualEqual => LiteralValue::Boolean(left == right),
-                    TokenType::BangEqual => LiteralValue::Boolean(left != right),
-                    _ => unimplemented!("Unsupported operation for booleans"),
-                }
-            }
+    ) -> Result<LiteralValue, RuntimeError> {
+        let left = self.visit_expression(left)?;
+        let right = self.visit_expression(right)?;
+
+        // equality is defined for any pair of values, values of different types are never equal
+        match operator.token_type {
+            TokenType::EqualEqual => return Ok(LiteralValue::Boolean(left == right)),
+            TokenType::BangEqual => return Ok(LiteralValue::Boolean(left != right)),
+            _ => {}
+        }
+
+        match (left, right) {
             (LiteralValue::Number(left), LiteralValue::Number(right)) => {
-                match operator.token_type {
-                    TokenType::EqualEqual => LiteralValue::Boolean(left == right),
-                    TokenType::BangEqual => LiteralValue::Boolean(left != right),
+                let value = match operator.token_type {
                     TokenType::Plus => LiteralValue::Number(left + right),
                     TokenType::Minus => LiteralValue::Number(left - right),
                     TokenType::Slash => LiteralValue::Number(left / right),
@@ -36,137 +133,1411 @@ impl Visitor<LiteralValue> for Interpreter {
                     TokenType::GreaterEqual => LiteralValue::Boolean(left >= right),
                     TokenType::Less => LiteralValue::Boolean(left < right),
                     TokenType::LessEqual => LiteralValue::Boolean(left <= right),
-                    _ => unimplemented!("Unsupported operation for two numbers"),
-                }
+                    _ => return Err(RuntimeError::new(operator, "Unsupported binary operator.")),
+                };
+                Ok(value)
             }
-            (LiteralValue::String(left), LiteralValue::String(right)) => {
-                match operator.token_type {
-                    TokenType::Plus => {
-                        let mut new_string = left;
-                        new_string.push_str(&right);
-                        LiteralValue::String(new_string)
-                    }
-                    _ => unimplemented!("Unsupported operation for two strings"),
-                }
+            (LiteralValue::String(left), LiteralValue::String(right))
+                if operator.token_type == TokenType::Plus =>
+            {
+                let mut new_string = String::with_capacity(left.len() + right.len());
+                new_string.push_str(&left);
+                new_string.push_str(&right);
+                Ok(LiteralValue::String(Rc::from(new_string)))
             }
+            _ if operator.token_type == TokenType::Plus => Err(RuntimeError::new(
+                operator,
+                "Operands must be two numbers or two strings.",
+            )),
+            _ => Err(RuntimeError::new(operator, "Operands must be numbers.")),
+        }
+    }
+
+    fn visit_call_expression(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> Result<LiteralValue, RuntimeError> {
+        let callee = self.visit_expression(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.visit_expression(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let function: &dyn Callable = match &callee {
+            LiteralValue::Function(function) => function.as_ref(),
+            LiteralValue::NativeFunction(function) => function.as_ref(),
+            LiteralValue::Class(class) => class,
             _ => {
-                unimplemented!("Operands have different types!");
+                return Err(RuntimeError::new(
+                    paren,
+                    "Can only call functions and classes.",
+                ))
+            }
+        };
+
+        if arguments.len() != function.arity() {
+            return Err(RuntimeError::new(
+                paren,
+                &format!(
+                    "Expected {} arguments but got {}.",
+                    function.arity(),
+                    arguments.len()
+                ),
+            ));
+        }
+
+        // the top level takes up a frame on the vm, so it does here too
+        if self.depth == FRAMES_MAX - 1 {
+            return Err(RuntimeError::new(paren, "Stack overflow."));
+        }
+
+        self.depth += 1;
+        let result = function.call(self, arguments);
+        self.depth -= 1;
+
+        result.map_err(|error| {
+            if error.is_located() {
+                error
+            } else {
+                RuntimeError::new(paren, &error.message)
             }
+        })
+    }
+
+    fn visit_get_expression(
+        &mut self,
+        object: &Expr,
+        name: &Token,
+    ) -> Result<LiteralValue, RuntimeError> {
+        match self.visit_expression(object)? {
+            LiteralValue::Instance(instance) => instance.get(name),
+            _ => Err(RuntimeError::new(name, "Only instances have properties.")),
         }
     }
 
-    fn visit_grouping_expression(&mut self, expr: &Expr) -> LiteralValue {
+    fn visit_grouping_expression(&mut self, expr: &Expr) -> Result<LiteralValue, RuntimeError> {
         self.visit_expression(expr)
     }
 
-    fn visit_literal_expression(&mut self, value: &LiteralValue) -> LiteralValue {
-        value.clone()
+    fn visit_literal_expression(
+        &mut self,
+        value: &LiteralValue,
+    ) -> Result<LiteralValue, RuntimeError> {
+        Ok(value.clone())
+    }
+
+    fn visit_logical_expression(
+        &mut self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let left = self.visit_expression(left)?;
+
+        // short-circuit: the operand that decided the result is returned as is
+        match operator.token_type {
+            TokenType::Or if left.is_truthy() => Ok(left),
+            TokenType::And if !left.is_truthy() => Ok(left),
+            _ => self.visit_expression(right),
+        }
+    }
+
+    fn visit_set_expression(
+        &mut self,
+        object: &Expr,
+        name: &Token,
+        value: &Expr,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let LiteralValue::Instance(instance) = self.visit_expression(object)? else {
+            return Err(RuntimeError::new(name, "Only instances have fields."));
+        };
+
+        let value = self.visit_expression(value)?;
+        instance.set(name, value.clone());
+        Ok(value)
     }
 
-    fn visit_unary_expression(&mut self, operator: &Token, expr: &Expr) -> LiteralValue {
-        let right = self.visit_expression(expr);
+    fn visit_super_expression(
+        &mut self,
+        keyword: &Token,
+        method: &Token,
+        binding: &Binding,
+    ) -> Result<LiteralValue, RuntimeError> {
+        // `this` is alone in the scope right inside the one holding `super`
+        let local = binding.get().expect("'super' was not resolved");
+        let this = Local {
+            depth: local.depth - 1,
+            slot: 0,
+        };
+        let superclass = self.scope().borrow().get_at(local);
+        let instance = self.scope().borrow().get_at(this);
 
-        match right {
-            LiteralValue::Boolean(boolean_value) => {
-                if operator.token_type == TokenType::Bang {
-                    LiteralValue::Boolean(!boolean_value)
-                } else {
-                    unimplemented!("Only negation operator is supported for booleans");
-                }
+        let (LiteralValue::Class(superclass), LiteralValue::Instance(instance)) =
+            (superclass, instance)
+        else {
+            return Err(RuntimeError::new(keyword, "Invalid use of 'super'."));
+        };
+
+        match superclass.find_method(&method.lexeme) {
+            Some(function) => Ok(LiteralValue::Function(Rc::new(function.bind(instance)))),
+            None => Err(RuntimeError::new(
+                method,
+                &format!("Undefined property '{}'.", method.lexeme),
+            )),
+        }
+    }
+
+    fn visit_this_expression(
+        &mut self,
+        keyword: &Token,
+        binding: &Binding,
+    ) -> Result<LiteralValue, RuntimeError> {
+        self.look_up_variable(keyword, binding)
+    }
+
+    fn visit_unary_expression(
+        &mut self,
+        operator: &Token,
+        expr: &Expr,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let right = self.visit_expression(expr)?;
+
+        match operator.token_type {
+            TokenType::Bang => Ok(LiteralValue::Boolean(!right.is_truthy())),
+            TokenType::Minus => match right {
+                LiteralValue::Number(number) => Ok(LiteralValue::Number(-number)),
+                _ => Err(RuntimeError::new(operator, "Operand must be a number.")),
+            },
+            _ => Err(RuntimeError::new(operator, "Unsupported unary operator.")),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expr) -> Result<LiteralValue, RuntimeError> {
+        match expr {
+            Expr::Binary(left, operator, right, _) => {
+                self.visit_binary_expression(left, operator, right)
             }
-            LiteralValue::Nil => {
-                unimplemented!("Unary operator cannot be applied to \"nil\"");
+            Expr::Call(callee, paren, arguments, _) => {
+                self.visit_call_expression(callee, paren, arguments)
             }
-            LiteralValue::Number(number) => {
-                if operator.token_type == TokenType::Minus {
-                    LiteralValue::Number(-number)
-                } else {
-                    unimplemented!("Only negation operator is supported for numbers");
-                }
+            Expr::Get(object, name, _) => self.visit_get_expression(object, name),
+            Expr::Grouping(expr, _) => self.visit_grouping_expression(expr),
+            Expr::Literal(literal_value, _) => self.visit_literal_expression(literal_value),
+            Expr::Logical(left, operator, right, _) => {
+                self.visit_logical_expression(left, operator, right)
+            }
+            Expr::Set(object, name, value, _) => self.visit_set_expression(object, name, value),
+            Expr::Super(keyword, method, binding, _) => {
+                self.visit_super_expression(keyword, method, binding)
             }
-            LiteralValue::String(_) => {
-                unimplemented!("Unary operator cannot be applied to \"String\"");
+            Expr::This(keyword, binding, _) => self.visit_this_expression(keyword, binding),
+            Expr::Unary(operator, expr, _) => self.visit_unary_expression(operator, expr),
+            Expr::Variable(token, binding, _) => self.visit_variable_expression(token, binding),
+            Expr::Assignment(token, expr, binding, _) => {
+                self.visit_assignment_expression(token, expr, binding)
             }
         }
     }
 
-    fn visit_expression(&mut self, expr: &Expr) -> LiteralValue {
-        match expr {
-            Expr::Binary(left, operator, right) => {
-                self.visit_binary_expression(left, operator, right)
+    fn visit_variable_expression(
+        &mut self,
+        name_token: &Token,
+        binding: &Binding,
+    ) -> Result<LiteralValue, RuntimeError> {
+        self.look_up_variable(name_token, binding)
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) -> Result<LiteralValue, RuntimeError> {
+        let environment = Environment::new(self.environment.clone());
+        self.execute_block(statements, Rc::new(RefCell::new(environment)))?;
+
+        Ok(LiteralValue::Nil)
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) -> Result<LiteralValue, RuntimeError> {
+        match statement {
+            Statement::Expression(expr, _) => self.visit_expression(expr),
+            Statement::Print(expr, span) => {
+                let val = self.visit_expression(expr)?;
+                writeln!(self.output, "{val}").map_err(|err| {
+                    RuntimeError::new(
+                        &print_keyword(*span),
+                        &format!("Could not write output: {err}."),
+                    )
+                })?;
+                Ok(val)
+            }
+            Statement::Var(token, initializer, _) => {
+                self.visit_var_declaration_statement(token, initializer)
+            }
+            Statement::Block(statements, _) => self.visit_block(statements),
+            Statement::If(condition, then_branch, else_branch, _) => {
+                self.visit_if_statement(condition, then_branch, else_branch)
+            }
+            Statement::While(condition, body, _) => self.visit_while_statement(condition, body),
+            Statement::Function(declaration) => self.visit_function_statement(declaration),
+            Statement::Return(keyword, value, _) => self.visit_return_statement(keyword, value),
+            Statement::Class(name, superclass, methods, _) => {
+                self.visit_class_statement(name, superclass, methods)
             }
-            Expr::Grouping(expr) => self.visit_grouping_expression(expr),
-            Expr::Literal(literal_value) => self.visit_literal_expression(literal_value),
-            Expr::Unary(operator, expr) => self.visit_unary_expression(operator, expr),
-            Expr::Variable(token) => self.visit_variable_expression(token),
-            Expr::Assignment(token, expr) => self.visit_assignment_expression(token, expr),
         }
     }
 
-    fn visit_variable_expression(&mut self, name_token: &Token) -> LiteralValue {
-        self.environment.get(&name_token.lexeme)
+    fn visit_class_statement(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) -> Result<LiteralValue, RuntimeError> {
+        let superclass = match superclass {
+            Some(superclass) => match self.visit_expression(superclass)? {
+                LiteralValue::Class(class) => Some(class),
+                _ => {
+                    let token = match superclass {
+                        Expr::Variable(token, _, _) => token,
+                        _ => name,
+                    };
+                    return Err(RuntimeError::new(token, "Superclass must be a class."));
+                }
+            },
+            None => None,
+        };
+
+        // methods of a subclass close over an extra scope holding `super`
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment = Environment::new(self.environment.clone());
+                environment.define(LiteralValue::Class(Rc::clone(superclass)));
+                Some(Rc::new(RefCell::new(environment)))
+            }
+            None => self.environment.clone(),
+        };
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let function = LoxFunction::new(
+                    Rc::clone(method),
+                    closure.clone(),
+                    &*method.name.lexeme == "init",
+                );
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let class = LoxClass::new(Rc::clone(&name.lexeme), superclass, methods);
+        self.define(name, LiteralValue::Class(Rc::new(class)));
+
+        Ok(LiteralValue::Nil)
+    }
+
+    fn visit_function_statement(
+        &mut self,
+        declaration: &Rc<FunctionDeclaration>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let function = LoxFunction::new(Rc::clone(declaration), self.environment.clone(), false);
+        self.define(&declaration.name, LiteralValue::Function(Rc::new(function)));
+
+        Ok(LiteralValue::Nil)
     }
 
-    fn visit_block(&mut self, statements: &Vec<Statement>) -> LiteralValue {
-        let previous_env = self.environment.clone();
+    fn visit_return_statement(
+        &mut self,
+        _keyword: &Token,
+        value: &Option<Expr>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let value = match value {
+            Some(expr) => self.visit_expression(expr)?,
+            None => LiteralValue::Nil,
+        };
+        self.return_value = Some(value.clone());
 
-        self.environment = Environment::new(Some(Box::new(self.environment.clone())));
+        Ok(value)
+    }
 
-        for s in statements {
-            self.visit_statement(&s);
+    fn visit_while_statement(
+        &mut self,
+        condition: &Expr,
+        body: &Statement,
+    ) -> Result<LiteralValue, RuntimeError> {
+        while self.return_value.is_none() && self.visit_expression(condition)?.is_truthy() {
+            self.visit_statement(body)?;
         }
-        self.environment = previous_env;
 
-        LiteralValue::Nil
+        Ok(LiteralValue::Nil)
     }
 
-    fn visit_statement(&mut self, statement: &Statement) -> LiteralValue {
-        match statement {
-            Statement::Expression(expr) => self.visit_expression(expr),
-            Statement::Print(expr) => {
-                let val = self.visit_expression(expr);
-                println!("{val}");
-                val
-            }
-            Statement::Var(token, initializer) => {
-                self.visit_var_declaration_statement(token, initializer)
-            }
-            Statement::Block(statements) => self.visit_block(statements),
+    fn visit_if_statement(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Statement,
+        else_branch: &Option<Box<Statement>>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        if self.visit_expression(condition)?.is_truthy() {
+            self.visit_statement(then_branch)?;
+        } else if let Some(else_branch) = else_branch {
+            self.visit_statement(else_branch)?;
         }
+
+        Ok(LiteralValue::Nil)
     }
 
-    fn visit_assignment_expression(&mut self, name_token: &Token, right: &Expr) -> LiteralValue {
-        let value = self.visit_expression(right);
-        self.environment.assign(&name_token.lexeme, value.clone());
+    fn visit_assignment_expression(
+        &mut self,
+        name_token: &Token,
+        right: &Expr,
+        binding: &Binding,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let value = self.visit_expression(right)?;
 
-        value
+        match binding.get() {
+            Some(local) => self.scope().borrow_mut().assign_at(local, value.clone()),
+            None => match self.globals.get_mut(&name_token.lexeme) {
+                Some(global) => *global = value.clone(),
+                None => return Err(undefined_variable(name_token)),
+            },
+        }
+
+        Ok(value)
     }
 
     fn visit_var_declaration_statement(
         &mut self,
         token: &Token,
         initializer: &Option<Expr>,
-    ) -> LiteralValue {
+    ) -> Result<LiteralValue, RuntimeError> {
         let mut value = LiteralValue::Nil;
         if let Some(default_initializer) = initializer {
-            value = self.visit_expression(default_initializer);
+            value = self.visit_expression(default_initializer)?;
         }
 
-        self.environment.define(&token.lexeme, value.clone());
-        value
+        self.define(token, value.clone());
+        Ok(value)
+    }
+}
+
+fn undefined_variable(name: &Token) -> RuntimeError {
+    RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
+
+// the `print` keyword at the start of a print statement, for errors about the statement
+fn print_keyword(span: Span) -> Token {
+    Token {
+        lexeme: intern("print"),
+        token_type: TokenType::Print,
+        line: span.line,
+        span: Span {
+            end: span.start + "print".len(),
+            ..span
+        },
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Interpreter {
+    // prints to stdout and reports errors to stderr
     pub fn new() -> Self {
+        Self::with_output(io::stdout(), io::stderr())
+    }
+
+    pub fn with_output(output: impl Write + 'static, errors: impl Write + 'static) -> Self {
+        let mut globals = HashMap::new();
+        for native in native::builtins() {
+            globals.insert(
+                intern(native.name()),
+                LiteralValue::NativeFunction(Rc::new(native)),
+            );
+        }
+
         Self {
-            environment: Environment::new(None),
+            globals,
+            environment: None,
+            return_value: None,
+            output: Box::new(output),
+            errors: Box::new(errors),
+            optimize: true,
+            depth: 0,
+        }
+    }
+
+    // the optimizer is on by default, turning it off runs the statements just as parsed
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    // scans, parses, resolves and runs `source`, stopping at the first runtime error;
+    // the value of a final expression statement is returned, so `1 + 2;` gives back 3
+    pub fn run(&mut self, source: &str) -> Result<Option<LiteralValue>, Error> {
+        let source_id = next_source_id();
+        let mut statements = crate::parse_source(source, source_id)?;
+        // decided before optimizing, which can turn other statements into expressions
+        let gives_value = matches!(statements.last(), Some(Statement::Expression(..)));
+        if self.optimize {
+            statements = optimizer::optimize(statements);
         }
+
+        let last = if gives_value { statements.pop() } else { None };
+        let runtime_error = |error: RuntimeError| Error::Runtime(error.within(source_id));
+        self.at_top_level(|interpreter| {
+            interpreter.interpret(statements).map_err(runtime_error)?;
+
+            match last {
+                Some(Statement::Expression(expr, _)) => {
+                    interpreter.evaluate(&expr).map(Some).map_err(runtime_error)
+                }
+                _ => Ok(None),
+            }
+        })
+    }
+
+    // writes the diagnostics for `error` to the error sink, `source` must be what was run
+    pub fn report(
+        &mut self,
+        error: &Error,
+        file_name: &str,
+        source: &str,
+        color: bool,
+    ) -> io::Result<()> {
+        error.report(&mut self.errors, file_name, source, color)
+    }
+
+    // makes `function` callable from Lox as the global `name`, replacing whatever was there
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[LiteralValue]) -> Result<LiteralValue, RuntimeError>
+            + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+        self.globals
+            .insert(intern(name), LiteralValue::NativeFunction(Rc::new(native)));
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LiteralValue> {
+        self.globals.get(name).cloned()
+    }
+
+    // defines the global, or replaces its value if it already exists
+    pub fn set_global(&mut self, name: &str, value: impl Into<LiteralValue>) {
+        self.globals.insert(intern(name), value.into());
+    }
+
+    // stops at the first runtime error, the interpreter can still be used afterwards
+    pub(crate) fn interpret(&mut self, statements: Vec<Statement>) -> Result<(), RuntimeError> {
+        self.at_top_level(|interpreter| {
+            statements
+                .iter()
+                .try_for_each(|statement| interpreter.visit_statement(statement).map(|_| ()))
+        })
+    }
+
+    // runs `f` in the global scope with no `return` unwinding; a native function can run more
+    // source while a Lox function is running, and that source does not belong to the function
+    fn at_top_level<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let environment = self.environment.take();
+        let return_value = self.return_value.take();
+        let result = f(self);
+        self.environment = environment;
+        self.return_value = return_value;
+
+        result
     }
 
-    pub fn interpret(&mut self, statements: Vec<Statement>) {
-        for statement in statements {
-            self.visit_statement(&statement);
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<LiteralValue, RuntimeError> {
+        self.visit_expression(expr)
+    }
+
+    // runs the statements with `environment` as the current scope, stopping early
+    // if a `return` is being unwound
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[Statement],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), RuntimeError> {
+        let previous = self.environment.replace(environment);
+
+        let result = statements.iter().try_for_each(|s| {
+            if self.return_value.is_none() {
+                self.visit_statement(s)?;
+            }
+            Ok(())
+        });
+        // the previous scope has to be restored even if a runtime error is unwinding
+        self.environment = previous;
+
+        result
+    }
+
+    fn look_up_variable(
+        &self,
+        name: &Token,
+        binding: &Binding,
+    ) -> Result<LiteralValue, RuntimeError> {
+        match binding.get() {
+            Some(local) => Ok(self.scope().borrow().get_at(local)),
+            None => self
+                .globals
+                .get(&name.lexeme)
+                .cloned()
+                .ok_or_else(|| undefined_variable(name)),
         }
     }
+
+    // the scope that resolved locals are found from
+    fn scope(&self) -> &Rc<RefCell<Environment>> {
+        self.environment
+            .as_ref()
+            .expect("Locals are only resolved inside a scope")
+    }
+
+    // the scope the resolver saw the declaration in: the global one at the top level, where
+    // variables are kept by name, and otherwise the current scope's next slot
+    fn define(&mut self, name: &Token, value: LiteralValue) {
+        match &self.environment {
+            Some(environment) => environment.borrow_mut().define(value),
+            None => {
+                self.globals.insert(name.lexeme.clone(), value);
+            }
+        }
+    }
+
+    pub(crate) fn take_return_value(&mut self) -> Option<LiteralValue> {
+        self.return_value.take()
+    }
+}
+
+#[cfg(test)]
+fn interpret_source(source: &str) -> Interpreter {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+    Resolver::new()
+        .resolve(&statements)
+        .expect("Resolving failed");
+
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .interpret(statements)
+        .expect("Interpreting failed");
+    interpreter
+}
+
+// what the source prints
+#[cfg(test)]
+fn output(source: &str) -> String {
+    let output = SharedBuffer::default();
+    let mut interpreter = Interpreter::with_output(output.clone(), io::sink());
+    interpreter.run(source).expect("Running failed");
+    output.contents()
+}
+
+#[cfg(test)]
+fn runtime_error(source: &str) -> String {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+    Resolver::new()
+        .resolve(&statements)
+        .expect("Resolving failed");
+
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .interpret(statements)
+        .expect_err("Interpreting succeeded")
+        .to_string()
+}
+
+#[test]
+fn test_truthiness() {
+    let interpreter = interpret_source(
+        "var a = !nil; var b = !0; var c = !\"\"; var d = !false; var e = !!true;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("a"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("b"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("c"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("d"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("e"),
+        Some(LiteralValue::Boolean(true))
+    );
+}
+
+#[test]
+fn test_if_else() {
+    let interpreter = interpret_source(
+        "var a; var b;
+        if (nil) a = 1; else a = 2;
+        if (0) b = 1; else b = 2;",
+    );
+
+    assert_eq!(interpreter.get_global("a"), Some(LiteralValue::Number(2.0)));
+    assert_eq!(interpreter.get_global("b"), Some(LiteralValue::Number(1.0)));
+}
+
+#[test]
+fn test_logical_operators_return_deciding_operand() {
+    let interpreter = interpret_source(
+        "var a = nil or \"yes\"; var b = 1 and 2; var c = false and 1; var d = 1 or 2;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("a"),
+        Some(LiteralValue::String(Rc::from("yes")))
+    );
+    assert_eq!(interpreter.get_global("b"), Some(LiteralValue::Number(2.0)));
+    assert_eq!(
+        interpreter.get_global("c"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(interpreter.get_global("d"), Some(LiteralValue::Number(1.0)));
+}
+
+#[test]
+fn test_logical_operators_short_circuit() {
+    let interpreter = interpret_source(
+        "var a = 0; var b = 0;
+        true or (a = 1);
+        false and (b = 1);",
+    );
+
+    assert_eq!(interpreter.get_global("a"), Some(LiteralValue::Number(0.0)));
+    assert_eq!(interpreter.get_global("b"), Some(LiteralValue::Number(0.0)));
+}
+
+#[test]
+fn test_block_assigns_to_enclosing_scope() {
+    let interpreter = interpret_source("var a = 1; { a = 2; var b = 3; }");
+
+    assert_eq!(interpreter.get_global("a"), Some(LiteralValue::Number(2.0)));
+}
+
+#[test]
+fn test_while_loop() {
+    let interpreter = interpret_source(
+        "var i = 0; var sum = 0;
+        while (i < 5) {
+            var next = i + 1;
+            sum = sum + next;
+            i = next;
+        }",
+    );
+
+    assert_eq!(interpreter.get_global("i"), Some(LiteralValue::Number(5.0)));
+    assert_eq!(
+        interpreter.get_global("sum"),
+        Some(LiteralValue::Number(15.0))
+    );
+}
+
+#[test]
+fn test_for_loop() {
+    let interpreter = interpret_source(
+        "var sum = 0;
+        for (var i = 0; i < 4; i = i + 1) {
+            var doubled = i * 2;
+            sum = sum + doubled;
+        }",
+    );
+
+    assert_eq!(
+        interpreter.get_global("sum"),
+        Some(LiteralValue::Number(12.0))
+    );
+}
+
+#[test]
+fn test_for_loop_loop_variable_is_scoped() {
+    let interpreter = interpret_source("var i = \"outer\"; for (var i = 0; i < 2; i = i + 1) {}");
+
+    assert_eq!(
+        interpreter.get_global("i"),
+        Some(LiteralValue::String(Rc::from("outer")))
+    );
+}
+
+#[test]
+fn test_for_loop_with_empty_clauses() {
+    let interpreter = interpret_source(
+        "var i = 0;
+        for (; i < 3;) i = i + 1;",
+    );
+
+    assert_eq!(interpreter.get_global("i"), Some(LiteralValue::Number(3.0)));
+}
+
+#[test]
+fn test_function_call() {
+    let interpreter = interpret_source(
+        "fun add(a, b) { return a + b; }
+        var result = add(1, 2);",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(3.0))
+    );
+}
+
+#[test]
+fn test_function_without_return_returns_nil() {
+    let interpreter = interpret_source(
+        "fun noop() {}
+        var result = noop();",
+    );
+
+    assert_eq!(interpreter.get_global("result"), Some(LiteralValue::Nil));
+}
+
+#[test]
+fn test_recursive_function() {
+    let interpreter = interpret_source(
+        "fun fib(n) {
+            if (n < 2) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        var result = fib(10);",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(55.0))
+    );
+}
+
+#[test]
+fn test_return_unwinds_nested_blocks_and_loops() {
+    let interpreter = interpret_source(
+        "fun find() {
+            for (var i = 0; i < 10; i = i + 1) {
+                {
+                    if (i == 3) return i;
+                }
+            }
+            return -1;
+        }
+        var result = find();
+        var after = \"still running\";",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(3.0))
+    );
+    assert_eq!(
+        interpreter.get_global("after"),
+        Some(LiteralValue::String(Rc::from("still running")))
+    );
+}
+
+#[test]
+fn test_function_arity_mismatch() {
+    assert_eq!(
+        runtime_error("fun add(a, b) { return a + b; } add(1);"),
+        "[line 1] Error: Expected 2 arguments but got 1."
+    );
+}
+
+#[test]
+fn test_call_non_function() {
+    assert_eq!(
+        runtime_error("\"not a function\"();"),
+        "[line 1] Error: Can only call functions and classes."
+    );
+}
+
+#[test]
+fn test_closure_counter() {
+    let output = output(
+        "fun make_counter() {
+            var count = 0;
+            fun increment() {
+                count = count + 1;
+                return count;
+            }
+            return increment;
+        }
+        var counter = make_counter();
+        print counter();
+        print counter();
+        print counter();",
+    );
+
+    assert_eq!(output, "1\n2\n3\n");
+}
+
+#[test]
+fn test_closures_have_independent_state() {
+    let interpreter = interpret_source(
+        "fun make_counter() {
+            var count = 0;
+            fun increment() {
+                count = count + 1;
+                return count;
+            }
+            return increment;
+        }
+        var first = make_counter();
+        var second = make_counter();
+        first();
+        first();
+        var a = first();
+        var b = second();",
+    );
+
+    assert_eq!(interpreter.get_global("a"), Some(LiteralValue::Number(3.0)));
+    assert_eq!(interpreter.get_global("b"), Some(LiteralValue::Number(1.0)));
+}
+
+#[test]
+fn test_closure_sees_later_assignments_to_captured_variable() {
+    let output = output(
+        "{
+            var message = \"before\";
+            fun show() { print message; }
+            show();
+            message = \"after\";
+            show();
+        }",
+    );
+
+    assert_eq!(output, "before\nafter\n");
+}
+
+#[test]
+fn test_instance_fields() {
+    let interpreter = interpret_source(
+        "class Box {}
+        var box = Box();
+        box.content = \"cat\";
+        var result = box.content;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::String(Rc::from("cat")))
+    );
+}
+
+#[test]
+fn test_methods_bind_this() {
+    let interpreter = interpret_source(
+        "class Counter {
+            increment() {
+                this.count = this.count + 1;
+                return this.count;
+            }
+        }
+        var counter = Counter();
+        counter.count = 10;
+        var increment = counter.increment;
+        increment();
+        var result = increment();",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(12.0))
+    );
+}
+
+#[test]
+fn test_initializer() {
+    let interpreter = interpret_source(
+        "class Point {
+            init(x, y) {
+                this.x = x;
+                this.y = y;
+            }
+            sum() { return this.x + this.y; }
+        }
+        var result = Point(1, 2).sum();",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(3.0))
+    );
+}
+
+#[test]
+fn test_initializer_always_returns_instance() {
+    let interpreter = interpret_source(
+        "class Thing {
+            init() {
+                this.ready = true;
+                return;
+            }
+        }
+        var thing = Thing();
+        thing.ready = false;
+        var again = thing.init();
+        again.marker = 1;
+        var ready = thing.ready;
+        var marker = thing.marker;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("ready"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("marker"),
+        Some(LiteralValue::Number(1.0))
+    );
+}
+
+#[test]
+fn test_class_arity_comes_from_initializer() {
+    assert_eq!(
+        runtime_error(
+            "class Point { init(x, y) {} }
+            Point();"
+        ),
+        "[line 2] Error: Expected 2 arguments but got 0."
+    );
+}
+
+#[test]
+fn test_undefined_property() {
+    assert_eq!(
+        runtime_error(
+            "class Empty {}
+            Empty().missing;"
+        ),
+        "[line 2] Error: Undefined property 'missing'."
+    );
+}
+
+#[test]
+fn test_inherited_methods() {
+    let interpreter = interpret_source(
+        "class Animal {
+            init(name) { this.name = name; }
+            describe() { return this.name + \" makes a sound\"; }
+        }
+        class Dog < Animal {}
+        var result = Dog(\"Rex\").describe();",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::String(Rc::from("Rex makes a sound")))
+    );
+}
+
+#[test]
+fn test_super_calls_overridden_method() {
+    let output = output(
+        "class A {
+            name() { return \"A\"; }
+        }
+        class B < A {
+            name() { return \"B\" + super.name(); }
+        }
+        class C < B {
+            name() { return \"C\" + super.name(); }
+        }
+        print C().name();
+        print B().name();",
+    );
+
+    assert_eq!(output, "CBA\nBA\n");
+}
+
+#[test]
+fn test_super_initializer() {
+    let interpreter = interpret_source(
+        "class Shape {
+            init(sides) { this.sides = sides; }
+        }
+        class Square < Shape {
+            init(size) {
+                super.init(4);
+                this.size = size;
+            }
+        }
+        var square = Square(3);
+        var result = square.sides * square.size;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(12.0))
+    );
+}
+
+#[test]
+fn test_inheriting_from_non_class() {
+    assert_eq!(
+        runtime_error(
+            "var NotAClass = \"nope\";
+            class Broken < NotAClass {}"
+        ),
+        "[line 2] Error: Superclass must be a class."
+    );
+}
+
+#[test]
+fn test_closure_binds_to_variable_in_scope_at_declaration() {
+    let interpreter = interpret_source(
+        "var a = \"global\";
+        var first;
+        var second;
+        {
+            fun show_a() { return a; }
+            first = show_a();
+            var a = \"block\";
+            second = show_a();
+        }",
+    );
+
+    assert_eq!(
+        interpreter.get_global("first"),
+        Some(LiteralValue::String(Rc::from("global")))
+    );
+    assert_eq!(
+        interpreter.get_global("second"),
+        Some(LiteralValue::String(Rc::from("global")))
+    );
+}
+
+#[test]
+fn test_locals_in_nested_scopes_keep_their_slots() {
+    let interpreter = interpret_source(
+        "var result;
+        fun f(a, b) {
+            var c = a + b;
+            {
+                var a = 10;
+                var d = c * a;
+                b = b + d;
+            }
+            return a + b + c + later;
+        }
+        var later = 100;
+        result = f(1, 2);",
+    );
+
+    assert_eq!(
+        interpreter.get_global("result"),
+        Some(LiteralValue::Number(1.0 + 32.0 + 3.0 + 100.0))
+    );
+}
+
+#[test]
+fn test_undefined_variable() {
+    assert_eq!(
+        runtime_error("print missing;"),
+        "[line 1] Error: Undefined variable 'missing'."
+    );
+    assert_eq!(
+        runtime_error("missing = 1;"),
+        "[line 1] Error: Undefined variable 'missing'."
+    );
+}
+
+#[test]
+fn test_operand_type_errors() {
+    assert_eq!(
+        runtime_error("-\"text\";"),
+        "[line 1] Error: Operand must be a number."
+    );
+    assert_eq!(
+        runtime_error("true < false;"),
+        "[line 1] Error: Operands must be numbers."
+    );
+    assert_eq!(
+        runtime_error("\"a\" - \"b\";"),
+        "[line 1] Error: Operands must be numbers."
+    );
+    assert_eq!(
+        runtime_error("1 < \"2\";"),
+        "[line 1] Error: Operands must be numbers."
+    );
+    assert_eq!(
+        runtime_error("nil * 2;"),
+        "[line 1] Error: Operands must be numbers."
+    );
+    assert_eq!(
+        runtime_error("\"a\" + 1;"),
+        "[line 1] Error: Operands must be two numbers or two strings."
+    );
+    assert_eq!(
+        runtime_error("true + true;"),
+        "[line 1] Error: Operands must be two numbers or two strings."
+    );
+}
+
+#[test]
+fn test_property_errors() {
+    assert_eq!(
+        runtime_error("var a = 1; a.field;"),
+        "[line 1] Error: Only instances have properties."
+    );
+    assert_eq!(
+        runtime_error("var a = 1; a.field = 2;"),
+        "[line 1] Error: Only instances have fields."
+    );
+}
+
+#[test]
+fn test_interpreter_is_usable_after_runtime_error() {
+    let (tokens, _) = Scanner::init(
+        "var a = 1;
+        fun fail() { { var b = 2; return -nil; } }
+        fail();",
+    )
+    .scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+    Resolver::new()
+        .resolve(&statements)
+        .expect("Resolving failed");
+
+    let mut interpreter = Interpreter::new();
+    assert!(interpreter.interpret(statements).is_err());
+    // the scopes of the failed call have been left again
+    assert!(interpreter.environment.is_none());
+    assert_eq!(interpreter.get_global("a"), Some(LiteralValue::Number(1.0)));
+}
+
+#[test]
+fn test_equality_of_mixed_types() {
+    let interpreter = interpret_source(
+        "var a = nil == nil;
+        var b = 1 == \"1\";
+        var c = nil == false;
+        var d = 0 == false;
+        var e = \"\" == nil;
+        var f = \"a\" == \"a\";
+        var g = true != \"true\";
+        var x;
+        var h = x == nil;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("a"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("b"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("c"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("d"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("e"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("f"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("g"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("h"),
+        Some(LiteralValue::Boolean(true))
+    );
+}
+
+#[test]
+fn test_nan_is_not_equal_to_itself() {
+    let interpreter = interpret_source(
+        "var nan = 0 / 0;
+        var a = nan == nan;
+        var b = nan != nan;
+        var c = nan == 0;
+        var d = nan < 0 or nan >= 0;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("a"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("b"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("c"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("d"),
+        Some(LiteralValue::Boolean(false))
+    );
+}
+
+#[test]
+fn test_equality_of_objects_is_identity() {
+    let interpreter = interpret_source(
+        "fun f() {}
+        fun g() {}
+        class A {}
+        var a = A();
+        var same_function = f == f;
+        var different_functions = f == g;
+        var same_class = A == A;
+        var same_instance = a == a;
+        var different_instances = A() == A();
+        var class_and_instance = A == a;",
+    );
+
+    assert_eq!(
+        interpreter.get_global("same_function"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("different_functions"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("same_class"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("same_instance"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("different_instances"),
+        Some(LiteralValue::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.get_global("class_and_instance"),
+        Some(LiteralValue::Boolean(false))
+    );
+}
+
+#[test]
+fn test_clock_is_a_global_native_function() {
+    let interpreter = interpret_source(
+        "var start = clock();
+        var later = clock();
+        var elapsed = later - start;
+        var is_native = clock == clock;",
+    );
+
+    let Some(LiteralValue::Number(elapsed)) = interpreter.get_global("elapsed") else {
+        panic!("clock did not return a number");
+    };
+    assert!(elapsed >= 0.0);
+    assert_eq!(
+        interpreter.get_global("is_native"),
+        Some(LiteralValue::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.get_global("clock").unwrap().to_string(),
+        "<native fn>"
+    );
+}
+
+#[test]
+fn test_native_function_arity_is_checked() {
+    assert_eq!(
+        runtime_error("clock(1);"),
+        "[line 1] Error: Expected 0 arguments but got 1."
+    );
+}
+
+#[test]
+fn test_native_errors_are_reported_at_the_call() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_native("half", 1, |_, arguments| {
+        Ok(LiteralValue::Number(native::number(arguments, 0)? / 2.0))
+    });
+
+    assert_eq!(
+        interpreter.run("half(3);"),
+        Ok(Some(LiteralValue::Number(1.5)))
+    );
+    assert_eq!(
+        interpreter
+            .run("var a = 1;\nhalf(\n\"a\");")
+            .map_err(|error| error.to_string()),
+        Err("[line 3] Error: Argument 1 must be a number.".to_string())
+    );
+}
+
+#[test]
+fn test_print_shows_values_plainly() {
+    assert_eq!(
+        output(
+            "class Point {}
+            fun origin() {}
+            print 1.5; print \"text\"; print nil; print true;
+            print Point; print Point(); print origin; print clock;"
+        ),
+        "1.5\ntext\nnil\ntrue\nPoint\nPoint instance\n<fn origin>\n<native fn>\n"
+    );
+}
+
+#[test]
+fn test_output_before_a_runtime_error_is_kept() {
+    let output = SharedBuffer::default();
+    let errors = SharedBuffer::default();
+    let mut interpreter = Interpreter::with_output(output.clone(), errors.clone());
+
+    let source = "print 1;\nprint -nil;\nprint 2;";
+    let error = interpreter.run(source).expect_err("Running succeeded");
+    interpreter
+        .report(&error, "test.lox", source, false)
+        .expect("Reporting failed");
+
+    assert_eq!(output.contents(), "1\n");
+    assert_eq!(
+        errors.contents(),
+        "[line 2] Error: Operand must be a number.
+ --> test.lox:2:7
+  |
+2 | print -nil;
+  |       ^
+"
+    );
+}
+
+#[test]
+fn test_errors_in_code_from_an_earlier_run_are_not_shown_against_a_later_one() {
+    let errors = SharedBuffer::default();
+    let mut interpreter = Interpreter::with_output(io::sink(), errors.clone());
+    interpreter.run("fun f() { return -nil; }").unwrap();
+
+    // the span of `-` in `f` lies on the `-` of the string here
+    let source = "print \"0123456789-\";f();";
+    let error = interpreter.run(source).expect_err("Running succeeded");
+    interpreter
+        .report(&error, "test.lox", source, false)
+        .expect("Reporting failed");
+
+    assert_eq!(
+        errors.contents(),
+        "[line 1] Error: Operand must be a number.\n"
+    );
 }
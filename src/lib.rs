@@ -0,0 +1,91 @@
+//! rslox is an interpreter for the Lox language from Crafting Interpreters, with a
+//! tree-walking backend (`Interpreter`) and a bytecode one (`Vm`).
+//!
+//! Embedding it only takes an `Interpreter`:
+//!
+//! ```
+//! use rslox::{native, Interpreter, Value};
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.set_global("width", 3.0);
+//! interpreter.define_native("double", 1, |_, arguments| {
+//!     Ok(Value::Number(native::number(arguments, 0)? * 2.0))
+//! });
+//! let area = interpreter.run("double(width * width);")?;
+//!
+//! assert_eq!(area, Some(Value::Number(18.0)));
+//! # Ok::<(), rslox::Error>(())
+//! ```
+//!
+//! `Interpreter::with_output` sends what scripts print, and the errors reported through
+//! `Interpreter::report`, somewhere other than stdout and stderr.
+mod callable;
+mod chunk;
+mod class;
+mod compiler;
+mod diagnostics;
+mod environment;
+mod error;
+mod expression;
+mod interner;
+mod interpreter;
+mod object;
+mod optimizer;
+mod parser;
+mod resolver;
+mod scanner;
+mod statement;
+#[cfg(test)]
+mod test_support;
+mod token;
+mod vm;
+
+// helpers for the arguments of native functions
+pub mod native;
+
+// what the rslox binary needs besides the backends
+pub mod conformance;
+pub mod disassembler;
+pub mod loxc;
+
+pub use crate::chunk::Function;
+pub use crate::error::Error;
+pub use crate::expression::LiteralValue as Value;
+pub use crate::interpreter::{Interpreter, RuntimeError};
+pub use crate::vm::Vm;
+
+use crate::compiler::Compiler;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::statement::Statement;
+use std::rc::Rc;
+
+// scans, parses and resolves `source`, what both backends do before running it
+pub(crate) fn parse(source: &str) -> Result<Vec<Statement>, Error> {
+    parse_source(source, token::next_source_id())
+}
+
+// like `parse`, with the spans in the source `source_id`
+pub(crate) fn parse_source(source: &str, source_id: u32) -> Result<Vec<Statement>, Error> {
+    let (tokens, lexical_errors) = Scanner::init(source).in_source(source_id).scan_tokens();
+    let statements = match Parser::init(&tokens).parse() {
+        Ok(statements) if lexical_errors.is_empty() => statements,
+        Ok(_) => return Err(Error::Syntax(lexical_errors, Vec::new())),
+        Err(parse_errors) => return Err(Error::Syntax(lexical_errors, parse_errors)),
+    };
+    Resolver::new()
+        .resolve(&statements)
+        .map_err(Error::Resolve)?;
+
+    Ok(statements)
+}
+
+// parses, optimizes (when `optimize` is set) and compiles `source` to the script the `Vm` runs
+pub fn compile(source: &str, optimize: bool) -> Result<Rc<Function>, Error> {
+    let mut statements = parse(source)?;
+    if optimize {
+        statements = optimizer::optimize(statements);
+    }
+    Compiler::new().compile(&statements).map_err(Error::Compile)
+}
@@ -0,0 +1,721 @@
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+#[cfg(test)]
+use crate::compiler::Compiler;
+use crate::interner::intern;
+#[cfg(test)]
+use crate::parser::Parser;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use crate::token::Span;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+// The `.loxc` format, a compiled script that runs without its source:
+//
+// magic     4 bytes  "LOXC"
+// version   u16      FORMAT_VERSION, files of other versions are refused
+// checksum  u32      CRC-32 of the rest of the file
+// script    function
+//
+// where a function is its name, arity and upvalue count, its code, a span for each byte of
+// code (the line table) and its constants; nested functions are stored inside the constants.
+// Integers are big endian like the operands in the code, lengths are u32 and strings are
+// UTF-8 prefixed with their length.
+pub const MAGIC: &[u8; 4] = b"LOXC";
+// to be bumped whenever the layout or the instruction set changes
+pub const FORMAT_VERSION: u16 = 1;
+
+// deeper than functions are nested in any script, it keeps a crafted file from overflowing
+// the stack while it is read
+const NESTING_MAX: usize = 256;
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+const FUNCTION: u8 = 2;
+
+// why a `.loxc` file could not be loaded
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    // the file does not start with the magic bytes
+    NotCompiled,
+    Version(u16),
+    // the checksum does not match, the file was damaged
+    Checksum,
+    // the file is intact but does not hold a valid program
+    Malformed(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotCompiled => write!(f, "Not a compiled Lox file."),
+            LoadError::Version(version) => write!(
+                f,
+                "Compiled for format version {version}, expected version {FORMAT_VERSION}."
+            ),
+            LoadError::Checksum => write!(f, "Checksum mismatch, the file is damaged."),
+            LoadError::Malformed(message) => write!(f, "Malformed bytecode: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+// the bytes of the `.loxc` file for a compiled script
+pub fn serialize(script: &Function) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_function(script, &mut body);
+
+    let mut bytes = Vec::with_capacity(body.len() + 10);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    bytes.extend_from_slice(&crc32(&body).to_be_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+// the script in a `.loxc` file, checked so that the `Vm` can run it as if it was just compiled
+pub fn deserialize(bytes: &[u8]) -> Result<Rc<Function>, LoadError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::NotCompiled);
+    }
+    let mut reader = Reader {
+        bytes,
+        position: MAGIC.len(),
+    };
+
+    let version = u16::from_be_bytes([reader.byte()?, reader.byte()?]);
+    if version != FORMAT_VERSION {
+        return Err(LoadError::Version(version));
+    }
+    let checksum = reader.u32()?;
+    if crc32(&bytes[reader.position..]) != checksum {
+        return Err(LoadError::Checksum);
+    }
+
+    let script = reader.function(0)?;
+    // the `Vm` calls the script with no arguments, as a closure that captured nothing
+    if !script.name.is_empty() || script.arity != 0 || script.upvalue_count != 0 {
+        return Err(malformed("the outermost function is not a script."));
+    }
+    if reader.position != bytes.len() {
+        return Err(malformed("trailing bytes after the script."));
+    }
+    Ok(script)
+}
+
+fn write_function(function: &Function, bytes: &mut Vec<u8>) {
+    write_str(&function.name, bytes);
+    write_u32(function.arity, bytes);
+    write_u32(function.upvalue_count, bytes);
+
+    let chunk = &function.chunk;
+    write_u32(chunk.code.len(), bytes);
+    bytes.extend_from_slice(&chunk.code);
+    for span in &chunk.spans {
+        write_u32(span.start, bytes);
+        write_u32(span.end, bytes);
+        bytes.extend_from_slice(&span.line.to_be_bytes());
+        bytes.extend_from_slice(&span.column.to_be_bytes());
+    }
+
+    write_u32(chunk.constants.len(), bytes);
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(number) => {
+                bytes.push(NUMBER);
+                bytes.extend_from_slice(&number.to_bits().to_be_bytes());
+            }
+            Constant::String(string) => {
+                bytes.push(STRING);
+                write_str(string, bytes);
+            }
+            Constant::Function(function) => {
+                bytes.push(FUNCTION);
+                write_function(function, bytes);
+            }
+        }
+    }
+}
+
+// the compiler keeps everything far below these limits
+fn write_u32(value: usize, bytes: &mut Vec<u8>) {
+    let value = u32::try_from(value).expect("Lengths fit in 32 bits");
+    bytes.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_str(string: &str, bytes: &mut Vec<u8>) {
+    write_u32(string.len(), bytes);
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], LoadError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(LoadError::Malformed("the file ends too early.".to_string()))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn length(&mut self) -> Result<usize, LoadError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.length()?;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| malformed("a string is not valid UTF-8."))
+    }
+
+    // `depth` is how many functions this one is nested in
+    fn function(&mut self, depth: usize) -> Result<Rc<Function>, LoadError> {
+        if depth == NESTING_MAX {
+            return Err(malformed("functions are nested too deeply."));
+        }
+        let name = self.string()?;
+        let arity = self.length()?;
+        let upvalue_count = self.length()?;
+
+        let code_length = self.length()?;
+        let code = self.take(code_length)?.to_vec();
+        let mut spans = Vec::new();
+        for _ in 0..code_length {
+            let start = self.length()?;
+            let end = self.length()?;
+            let line = self.u32()?;
+            let column = self.u32()?;
+            spans.push(Span::new(start, end, line, column));
+        }
+
+        let constant_count = self.length()?;
+        let mut constants = Vec::new();
+        for _ in 0..constant_count {
+            let constant = match self.byte()? {
+                NUMBER => {
+                    let bytes = self.take(8)?;
+                    let mut bits = [0; 8];
+                    bits.copy_from_slice(bytes);
+                    Constant::Number(f64::from_bits(u64::from_be_bytes(bits)))
+                }
+                STRING => Constant::String(intern(&self.string()?)),
+                FUNCTION => Constant::Function(self.function(depth + 1)?),
+                tag => return Err(malformed(&format!("unknown constant kind {tag}."))),
+            };
+            constants.push(constant);
+        }
+
+        let function = Function {
+            name,
+            arity,
+            upvalue_count,
+            chunk: Chunk {
+                code,
+                spans,
+                constants,
+            },
+        };
+        verify(&function)?;
+        Ok(Rc::new(function))
+    }
+}
+
+// The checks the compiler guarantees for its own output: every instruction is known, its
+// operands are inside the code, constants are of the kind the instruction expects and jumps
+// land on an instruction of the same function. `verify_stack` then follows every path through
+// the code, so that what is left can only fail at runtime with a runtime error.
+fn verify(function: &Function) -> Result<(), LoadError> {
+    let chunk = &function.chunk;
+    let name = function.display();
+    let length = chunk.code.len();
+
+    let mut starts = vec![false; length];
+    let mut jumps = Vec::new();
+    // the last instruction, not the last byte, which can be an operand
+    let mut last = None;
+    let mut offset = 0;
+    while offset < length {
+        let Some(op) = OpCode::from_byte(chunk.code[offset]) else {
+            return Err(malformed(&format!(
+                "unknown instruction {} at {offset} in {name}.",
+                chunk.code[offset]
+            )));
+        };
+        starts[offset] = true;
+        if offset + 1 + operand_count(op) > length {
+            return Err(malformed(&format!("{name} ends inside an instruction.")));
+        }
+
+        match op {
+            OpCode::Constant => {
+                constant(chunk, offset, &name)?;
+            }
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                let Constant::String(_) = constant(chunk, offset, &name)? else {
+                    return Err(malformed(&format!(
+                        "a name at {offset} in {name} is not a string."
+                    )));
+                };
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => jumps.push(offset),
+            OpCode::Closure => {
+                let Constant::Function(_) = constant(chunk, offset, &name)? else {
+                    return Err(malformed(&format!(
+                        "the closure at {offset} in {name} is not a function."
+                    )));
+                };
+            }
+            _ => {}
+        }
+
+        offset += instruction_size(chunk, offset, op);
+        if offset > length {
+            return Err(malformed(&format!("{name} ends inside an instruction.")));
+        }
+        last = Some(op);
+    }
+    // so that no instruction runs past the end of the code
+    if last != Some(OpCode::Return) {
+        return Err(malformed(&format!("{name} does not end with a return.")));
+    }
+
+    for offset in jumps {
+        let lands = jump_target(chunk, offset).is_some_and(|target| starts[target]);
+        if !lands {
+            return Err(malformed(&format!(
+                "the jump at {offset} in {name} does not land on an instruction."
+            )));
+        }
+    }
+
+    verify_stack(function)
+}
+
+// the stack of a call as `verify_stack` sees it before an instruction
+#[derive(Clone, PartialEq)]
+struct Stack {
+    height: usize,
+    // the slots a closure may have captured, they may only be taken off the stack by
+    // CLOSE_UPVALUE or RETURN, or their upvalues would be left pointing past the top
+    captured: BTreeSet<usize>,
+}
+
+// Follows every path through the code of `function`, which `verify` has checked otherwise,
+// and makes sure that instructions only take values that are on the stack, locals are
+// inside it, upvalues exist and the stack is as deep on every path to an instruction.
+fn verify_stack(function: &Function) -> Result<(), LoadError> {
+    let chunk = &function.chunk;
+    let name = function.display();
+
+    let mut stacks: Vec<Option<Stack>> = vec![None; chunk.code.len()];
+    // a call starts with the function (or the receiver of a method) and the arguments
+    let entry = Stack {
+        height: function.arity + 1,
+        captured: BTreeSet::new(),
+    };
+    let mut pending = vec![(0, entry)];
+
+    while let Some((offset, stack)) = pending.pop() {
+        let mut stack = match &stacks[offset] {
+            None => stack,
+            Some(known) if known.height != stack.height => {
+                return Err(malformed(&format!(
+                    "the stack is {} deep on one path to {offset} in {name} and {} on another.",
+                    known.height, stack.height
+                )));
+            }
+            Some(known) if stack.captured.is_subset(&known.captured) => continue,
+            Some(known) => Stack {
+                height: known.height,
+                captured: &known.captured | &stack.captured,
+            },
+        };
+        stacks[offset] = Some(stack.clone());
+
+        let op =
+            OpCode::from_byte(chunk.code[offset]).expect("Instructions are checked by `verify`");
+        let operand = chunk.code.get(offset + 1).copied().unwrap_or(0) as usize;
+        let (needs, pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetGlobal
+            | OpCode::GetLocal
+            | OpCode::GetUpvalue
+            | OpCode::Closure
+            | OpCode::Class => (0, 0, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0, 0),
+            OpCode::SetLocal | OpCode::SetGlobal | OpCode::SetUpvalue | OpCode::JumpIfFalse => {
+                (1, 0, 0)
+            }
+            OpCode::Pop
+            | OpCode::DefineGlobal
+            | OpCode::Print
+            | OpCode::CloseUpvalue
+            | OpCode::Return => (1, 1, 0),
+            OpCode::GetProperty | OpCode::Not | OpCode::Negate => (1, 1, 1),
+            OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => (2, 2, 1),
+            OpCode::Inherit | OpCode::Method => (2, 1, 0),
+            OpCode::Call => (operand + 1, operand + 1, 1),
+        };
+
+        if stack.height < needs {
+            return Err(malformed(&format!(
+                "the stack underflows at {offset} in {name}."
+            )));
+        }
+        let top = stack.height - pops;
+        match op {
+            OpCode::Return => continue,
+            OpCode::CloseUpvalue => {
+                stack.captured.remove(&top);
+            }
+            _ if stack.captured.range(top..).next().is_some() => {
+                return Err(malformed(&format!(
+                    "a captured variable is popped at {offset} in {name}."
+                )));
+            }
+            _ => {}
+        }
+
+        match op {
+            OpCode::GetLocal | OpCode::SetLocal if operand >= stack.height => {
+                return Err(malformed(&format!(
+                    "the local {operand} used at {offset} in {name} does not exist."
+                )));
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue if operand >= function.upvalue_count => {
+                return Err(malformed(&format!(
+                    "the upvalue {operand} used at {offset} in {name} does not exist."
+                )));
+            }
+            OpCode::Closure => {
+                let Constant::Function(closed) = constant(chunk, offset, &name)? else {
+                    unreachable!("`verify` checks that closures are of functions");
+                };
+                for upvalue in 0..closed.upvalue_count {
+                    let is_local = chunk.code[offset + 3 + 2 * upvalue];
+                    let index = chunk.code[offset + 4 + 2 * upvalue] as usize;
+                    match is_local {
+                        1 if index < stack.height => {
+                            stack.captured.insert(index);
+                        }
+                        0 if index < function.upvalue_count => {}
+                        _ => {
+                            return Err(malformed(&format!(
+                                "the closure at {offset} in {name} captures a variable that does \
+                                 not exist."
+                            )))
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        stack.height = top + pushes;
+
+        let next = offset + instruction_size(chunk, offset, op);
+        match op {
+            OpCode::Jump | OpCode::Loop => {
+                pending.push((
+                    jump_target(chunk, offset).expect("Jumps are checked by `verify`"),
+                    stack,
+                ));
+            }
+            OpCode::JumpIfFalse => {
+                let target = jump_target(chunk, offset).expect("Jumps are checked by `verify`");
+                pending.push((target, stack.clone()));
+                pending.push((next, stack));
+            }
+            _ => pending.push((next, stack)),
+        }
+    }
+    Ok(())
+}
+
+// the bytes after the instruction, without the upvalues of a closure
+fn operand_count(op: OpCode) -> usize {
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method
+        | OpCode::Jump
+        | OpCode::JumpIfFalse
+        | OpCode::Loop
+        | OpCode::Closure => 2,
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => 1,
+        _ => 0,
+    }
+}
+
+// the bytes the instruction at `offset` takes up, its constant has to exist
+fn instruction_size(chunk: &Chunk, offset: usize, op: OpCode) -> usize {
+    let size = 1 + operand_count(op);
+    if op != OpCode::Closure {
+        return size;
+    }
+    match chunk.constants.get(chunk.read_short(offset + 1)) {
+        Some(Constant::Function(closed)) => size + 2 * closed.upvalue_count,
+        _ => size,
+    }
+}
+
+// where the jump at `offset` goes to, if that is inside the function
+fn jump_target(chunk: &Chunk, offset: usize) -> Option<usize> {
+    let next = offset + 3;
+    let distance = chunk.read_short(offset + 1);
+    if chunk.code[offset] == OpCode::Loop as u8 {
+        next.checked_sub(distance)
+    } else {
+        Some(next + distance).filter(|&target| target < chunk.code.len())
+    }
+}
+
+fn constant<'a>(chunk: &'a Chunk, offset: usize, name: &str) -> Result<&'a Constant, LoadError> {
+    let index = chunk.read_short(offset + 1);
+    chunk.constants.get(index).ok_or_else(|| {
+        malformed(&format!(
+            "the constant {index} used at {offset} in {name} does not exist."
+        ))
+    })
+}
+
+fn malformed(message: &str) -> LoadError {
+    LoadError::Malformed(message.to_string())
+}
+
+// CRC-32 as used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+fn compile_source(source: &str) -> Rc<Function> {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+    Compiler::new()
+        .compile(&statements)
+        .expect("Compiling failed")
+}
+
+#[test]
+fn test_round_trip() {
+    let script = compile_source(
+        "class A { init(x) { this.x = x; } }
+        fun make() { var a = A(1.5); fun get() { return a.x; } return get; }
+        print make()() + 2;",
+    );
+
+    assert_eq!(deserialize(&serialize(&script)), Ok(script));
+}
+
+#[test]
+fn test_checksum_of_known_input() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn test_damaged_files_are_refused() {
+    let bytes = serialize(&compile_source("print 1;"));
+
+    assert_eq!(deserialize(b"print 1;"), Err(LoadError::NotCompiled));
+
+    let mut other_version = bytes.clone();
+    other_version[5] += 1;
+    assert_eq!(
+        deserialize(&other_version),
+        Err(LoadError::Version(FORMAT_VERSION + 1))
+    );
+
+    let mut flipped = bytes.clone();
+    *flipped.last_mut().unwrap() ^= 1;
+    assert_eq!(deserialize(&flipped), Err(LoadError::Checksum));
+
+    assert_eq!(
+        deserialize(&bytes[..bytes.len() - 1]),
+        Err(LoadError::Checksum)
+    );
+}
+
+#[test]
+fn test_invalid_bytecode_is_refused() {
+    // a constant that is not there, with a checksum that matches
+    let script = Function {
+        chunk: Chunk {
+            code: vec![OpCode::Constant as u8, 0, 0, OpCode::Return as u8],
+            spans: vec![Span::default(); 4],
+            constants: Vec::new(),
+        },
+        ..Function::default()
+    };
+
+    assert_eq!(
+        deserialize(&serialize(&script)),
+        Err(malformed(
+            "the constant 0 used at 0 in <script> does not exist."
+        ))
+    );
+}
+
+// what loading a script with `code` and `constants` as its bytecode gives
+#[cfg(test)]
+fn load(code: &[OpCode], operands: &[(usize, u8)], constants: Vec<Constant>) -> LoadError {
+    let mut bytes: Vec<u8> = code.iter().map(|&op| op as u8).collect();
+    for &(offset, operand) in operands {
+        bytes.insert(offset, operand);
+    }
+    let script = Function {
+        chunk: Chunk {
+            spans: vec![Span::default(); bytes.len()],
+            code: bytes,
+            constants,
+        },
+        ..Function::default()
+    };
+
+    deserialize(&serialize(&script)).expect_err("Loading succeeded")
+}
+
+#[test]
+fn test_only_scripts_are_loaded() {
+    let code = vec![
+        OpCode::GetUpvalue as u8,
+        0,
+        OpCode::Nil as u8,
+        OpCode::Return as u8,
+    ];
+    let script = |name: &str, arity, upvalue_count| Function {
+        name: name.to_string(),
+        arity,
+        upvalue_count,
+        chunk: Chunk {
+            spans: vec![Span::default(); code.len()],
+            code: code.clone(),
+            constants: Vec::new(),
+        },
+    };
+
+    for function in [script("", 0, 1), script("", 1, 1), script("f", 0, 1)] {
+        assert_eq!(
+            deserialize(&serialize(&function)),
+            Err(malformed("the outermost function is not a script."))
+        );
+    }
+}
+
+#[test]
+fn test_operands_that_look_like_a_return_do_not_end_the_code() {
+    let constants = (0..34).map(|i| Constant::Number(i as f64)).collect();
+
+    assert_eq!(
+        load(
+            &[OpCode::Constant],
+            &[(1, 0), (2, OpCode::Return as u8)],
+            constants
+        ),
+        malformed("<script> does not end with a return.")
+    );
+}
+
+#[test]
+fn test_bytecode_that_misuses_the_stack_is_refused() {
+    assert_eq!(
+        load(&[OpCode::GetLocal, OpCode::Return], &[(1, 50)], Vec::new()),
+        malformed("the local 50 used at 0 in <script> does not exist.")
+    );
+    assert_eq!(
+        load(&[OpCode::Pop, OpCode::Pop, OpCode::Return], &[], Vec::new()),
+        malformed("the stack underflows at 1 in <script>.")
+    );
+    assert_eq!(
+        load(&[OpCode::GetUpvalue, OpCode::Return], &[(1, 3)], Vec::new()),
+        malformed("the upvalue 3 used at 0 in <script> does not exist.")
+    );
+}
+
+#[test]
+fn test_jumps_into_an_instruction_are_refused() {
+    // over the NIL into the second byte of the jump's own operand
+    assert_eq!(
+        load(
+            &[OpCode::Nil, OpCode::Loop, OpCode::Return],
+            &[(2, 0), (3, 2)],
+            Vec::new()
+        ),
+        malformed("the jump at 1 in <script> does not land on an instruction.")
+    );
+}
+
+#[test]
+fn test_deeply_nested_functions_are_refused() {
+    let mut function = Function::default();
+    for _ in 0..NESTING_MAX + 1 {
+        function = Function {
+            chunk: Chunk {
+                constants: vec![Constant::Function(Rc::new(function))],
+                ..Chunk::default()
+            },
+            ..Function::default()
+        };
+    }
+
+    assert_eq!(
+        deserialize(&serialize(&function)),
+        Err(malformed("functions are nested too deeply."))
+    );
+}
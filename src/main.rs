@@ -1,46 +1,343 @@
-mod environment;
-mod expression;
-mod interpreter;
-mod parser;
-mod scanner;
-mod statement;
-mod token;
-
-use crate::interpreter::Interpreter;
-use crate::parser::Parser;
-use crate::scanner::Scanner;
-use std::io::{stdin, stdout, BufRead, Write};
-use std::{env, error, fs, process};
-
-fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.len() > 1 {
-        println!("Usage: rslox [script]");
-        process::exit(64);
-    } else if args.len() == 1 {
-        if let Err(err) = run_file(&args[0]) {
-            println!("Execution failed! {}", err);
-            process::exit(1);
-        }
-    } else if let Err(err) = run_prompt() {
-        println!("Execution failed! {}", err);
-        process::exit(1);
-    }
-}
-
-fn run_file(path: &str) -> Result<(), Box<dyn error::Error>> {
-    let file_bytes = fs::read(path)?;
-    let script_contents = String::from_utf8(file_bytes)?;
-    let mut interpreter = Interpreter::new();
-
-    run(&script_contents, &mut interpreter);
-    Ok(())
-}
-
-fn run_prompt() -> Result<(), Box<dyn error::Error>> {
+use rslox::disassembler::disassemble;
+use rslox::Function;
+use rslox::{conformance, loxc, Error, Interpreter, Vm};
+use std::ffi::OsStr;
+use std::io::{stderr, stdin, stdout, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::{env, fs, io, panic, thread};
+
+// exit codes from sysexits.h, the same ones the reference implementations use
+const EX_USAGE: u8 = 64;
+const EX_DATAERR: u8 = 65;
+const EX_SOFTWARE: u8 = 70;
+const EX_IOERR: u8 = 74;
+
+// why a piece of source could not be run, the errors themselves have already been reported
+enum Failure {
+    // lexical, syntax or resolution errors, nothing was executed
+    Static,
+    Runtime,
+}
+
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Failure::Static => ExitCode::from(EX_DATAERR),
+            Failure::Runtime => ExitCode::from(EX_SOFTWARE),
+        }
+    }
+}
+
+// the value of the `--color` flag
+#[derive(Clone, Copy)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // `auto` only colours terminals, and nothing at all when NO_COLOR is set
+    fn enabled(self, is_terminal: bool, no_color: Option<&OsStr>) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(|value| value.is_empty()),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// the value of the `--backend` flag
+#[derive(Clone, Copy)]
+enum Backend {
+    // the tree-walking `Interpreter`
+    Tree,
+    // the bytecode `Vm`
+    Vm,
+}
+
+impl Backend {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tree" => Some(Backend::Tree),
+            "vm" => Some(Backend::Vm),
+            _ => None,
+        }
+    }
+
+    fn flag(self) -> &'static str {
+        match self {
+            Backend::Tree => "--backend=tree",
+            Backend::Vm => "--backend=vm",
+        }
+    }
+}
+
+// a backend ready to run source, it keeps the globals from one run to the next
+enum Engine {
+    Tree(Interpreter),
+    Vm(Vm),
+}
+
+impl Engine {
+    fn new(options: &Options) -> Self {
+        match options.backend {
+            Backend::Tree => {
+                let mut interpreter = Interpreter::new();
+                interpreter.set_optimize(options.optimize);
+                Engine::Tree(interpreter)
+            }
+            Backend::Vm => {
+                let mut vm = Vm::new();
+                vm.set_optimize(options.optimize);
+                Engine::Vm(vm)
+            }
+        }
+    }
+
+    // gives the value of a final expression statement as the REPL shows it
+    fn run(&mut self, source: &str, color: bool) -> Result<Option<String>, Error> {
+        match self {
+            Engine::Tree(interpreter) => {
+                Ok(interpreter.run(source)?.map(|value| value.repr(color)))
+            }
+            Engine::Vm(vm) => Ok(vm.run(source)?.map(|value| value.repr(color))),
+        }
+    }
+
+    fn report(&mut self, error: &Error, file_name: &str, source: &str, color: bool) {
+        // there is nowhere left to report to if stderr is gone
+        let _ = match self {
+            Engine::Tree(interpreter) => interpreter.report(error, file_name, source, color),
+            Engine::Vm(vm) => vm.report(error, file_name, source, color),
+        };
+    }
+}
+
+struct Options {
+    // whether the values the REPL shows on stdout are coloured
+    color_values: bool,
+    // whether the diagnostics on stderr are coloured
+    color_errors: bool,
+    // whether the value of a final expression statement is shown, like in the REPL
+    echo: bool,
+    backend: Backend,
+    // whether the syntax tree goes through the optimizer, `--no-opt` turns it off
+    optimize: bool,
+}
+
+// the tree backend recurses on the native stack for every lox call, and a debug build needs more
+// than the main thread has to reach `FRAMES_MAX` of them
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() -> ExitCode {
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .expect("Could not spawn the interpreter thread.");
+    runner
+        .join()
+        .unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+fn run_command() -> ExitCode {
+    let mut color = ColorChoice::Auto;
+    let mut backend = Backend::Tree;
+    let mut disassemble = false;
+    let mut optimize = true;
+    let mut args = Vec::new();
+    for arg in env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--color=") {
+            match ColorChoice::parse(value) {
+                Some(choice) => color = choice,
+                None => return usage(),
+            }
+        } else if let Some(value) = arg.strip_prefix("--backend=") {
+            match Backend::parse(value) {
+                Some(choice) => backend = choice,
+                None => return usage(),
+            }
+        } else if arg == "--disassemble" {
+            disassemble = true;
+        } else if arg == "--no-opt" {
+            optimize = false;
+        } else if arg.starts_with("--") {
+            return usage();
+        } else {
+            args.push(arg);
+        }
+    }
+
+    // stdout and stderr are coloured each on their own, by passing the flags below along;
+    // `colored` would otherwise decide for both by whether stdout is a terminal
+    colored::control::set_override(true);
+    let no_color = env::var_os("NO_COLOR");
+    let mut options = Options {
+        color_values: color.enabled(stdout().is_terminal(), no_color.as_deref()),
+        color_errors: color.enabled(stderr().is_terminal(), no_color.as_deref()),
+        echo: false,
+        backend,
+        optimize,
+    };
+
+    match args.as_slice() {
+        [path] if disassemble => disassemble_file(path, &options),
+        _ if disassemble => usage(),
+        [] => {
+            options.echo = true;
+            if let Err(err) = run_prompt(&options) {
+                eprintln!("Could not read input: {err}");
+                return ExitCode::from(EX_IOERR);
+            }
+            ExitCode::SUCCESS
+        }
+        [path] if is_compiled(path) => run_compiled(path, &options),
+        [path] => run_file(path, &options),
+        [command, path, flag, output] if command == "compile" && flag == "-o" => {
+            compile_file(path, output, &options)
+        }
+        [command, directory] if command == "test" => run_tests(directory, &options),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: rslox [--color=auto|always|never] [--backend=tree|vm] [--no-opt] [script]");
+    eprintln!("       rslox [--backend=tree|vm] [--no-opt] test <directory>");
+    eprintln!("       rslox [--no-opt] compile <script> -o <script.loxc>");
+    eprintln!("       rslox [--color=auto|always|never] <script.loxc>");
+    eprintln!("       rslox [--no-opt] --disassemble <script or script.loxc>");
+    ExitCode::from(EX_USAGE)
+}
+
+fn read_script(path: &str) -> Result<String, ExitCode> {
+    fs::read_to_string(path).map_err(|err| {
+        eprintln!("Could not read file '{path}': {err}");
+        ExitCode::from(EX_IOERR)
+    })
+}
+
+fn run_file(path: &str, options: &Options) -> ExitCode {
+    let script_contents = match read_script(path) {
+        Ok(script_contents) => script_contents,
+        Err(exit_code) => return exit_code,
+    };
+    let mut engine = Engine::new(options);
+
+    match run(path, &script_contents, &mut engine, options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => failure.exit_code(),
+    }
+}
+
+// prints the bytecode the script compiles to instead of running it
+fn disassemble_file(path: &str, options: &Options) -> ExitCode {
+    let script = if is_compiled(path) {
+        load_compiled(path)
+    } else {
+        compile_script(path, options)
+    };
+    match script {
+        Ok(script) => {
+            print!("{}", disassemble(&script));
+            ExitCode::SUCCESS
+        }
+        Err(exit_code) => exit_code,
+    }
+}
+
+// compiled scripts are told apart by their extension
+fn is_compiled(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == "loxc")
+}
+
+// the compiled script, or the exit code after its errors have been reported
+fn compile_script(path: &str, options: &Options) -> Result<Rc<Function>, ExitCode> {
+    let script_contents = read_script(path)?;
+    rslox::compile(&script_contents, options.optimize).map_err(|error| {
+        let _ = error.report(&mut stderr(), path, &script_contents, options.color_errors);
+        Failure::Static.exit_code()
+    })
+}
+
+// saves the bytecode of the script as a `.loxc` file that runs without being parsed again
+fn compile_file(path: &str, output: &str, options: &Options) -> ExitCode {
+    let script = match compile_script(path, options) {
+        Ok(script) => script,
+        Err(exit_code) => return exit_code,
+    };
+
+    match fs::write(output, loxc::serialize(&script)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Could not write file '{output}': {err}");
+            ExitCode::from(EX_IOERR)
+        }
+    }
+}
+
+fn load_compiled(path: &str) -> Result<Rc<Function>, ExitCode> {
+    let bytes = fs::read(path).map_err(|err| {
+        eprintln!("Could not read file '{path}': {err}");
+        ExitCode::from(EX_IOERR)
+    })?;
+    loxc::deserialize(&bytes).map_err(|err| {
+        eprintln!("Could not load '{path}': {err}");
+        ExitCode::from(EX_DATAERR)
+    })
+}
+
+// compiled scripts can only run on the `Vm`, whatever the backend; without the source their
+// runtime errors are reported with the line alone
+fn run_compiled(path: &str, options: &Options) -> ExitCode {
+    let script = match load_compiled(path) {
+        Ok(script) => script,
+        Err(exit_code) => return exit_code,
+    };
+
+    let mut vm = Vm::new();
+    match vm.execute(script) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => {
+            let _ = vm.report(&Error::Runtime(error), path, "", options.color_errors);
+            Failure::Runtime.exit_code()
+        }
+    }
+}
+
+fn run_tests(directory: &str, options: &Options) -> ExitCode {
+    let mut arguments = vec![options.backend.flag()];
+    if !options.optimize {
+        arguments.push("--no-opt");
+    }
+    match conformance::run_suite(Path::new(directory), &arguments) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("Could not run the tests in '{directory}': {err}");
+            ExitCode::from(EX_IOERR)
+        }
+    }
+}
+
+// errors in the REPL are reported but do not end the session
+fn run_prompt(options: &Options) -> io::Result<()> {
     let stdin = stdin();
     let mut line = String::new();
-    let mut interpreter = Interpreter::new();
+    let mut engine = Engine::new(options);
 
     loop {
         line.clear();
@@ -52,17 +349,51 @@ fn run_prompt() -> Result<(), Box<dyn error::Error>> {
         handle.read_line(&mut line)?;
 
         if line.trim().is_empty() {
-            process::exit(0);
+            return Ok(());
         }
 
-        run(&line, &mut interpreter);
+        let _ = run("<stdin>", &line, &mut engine, options);
     }
 }
 
-fn run(program_contents: &str, interpreter: &mut Interpreter) {
-    let tokens = Scanner::init(program_contents).scan_tokens();
-    let mut parser = Parser::init(&tokens);
-    let statements = parser.parse();
+fn run(
+    file_name: &str,
+    program_contents: &str,
+    engine: &mut Engine,
+    options: &Options,
+) -> Result<(), Failure> {
+    match engine.run(program_contents, options.color_values) {
+        Ok(value) => {
+            if let (true, Some(value)) = (options.echo, value) {
+                println!("{value}");
+            }
+            Ok(())
+        }
+        Err(error) => {
+            engine.report(&error, file_name, program_contents, options.color_errors);
+
+            if error.is_runtime() {
+                Err(Failure::Runtime)
+            } else {
+                Err(Failure::Static)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_auto_colors_only_terminals() {
+    assert!(ColorChoice::Auto.enabled(true, None));
+    assert!(!ColorChoice::Auto.enabled(false, None));
+    assert!(ColorChoice::Always.enabled(false, None));
+    assert!(!ColorChoice::Never.enabled(true, None));
+}
+
+#[test]
+fn test_no_color_turns_off_auto_only() {
+    let no_color = Some(OsStr::new("1"));
 
-    interpreter.interpret(statements);
+    assert!(!ColorChoice::Auto.enabled(true, no_color));
+    assert!(ColorChoice::Auto.enabled(true, Some(OsStr::new(""))));
+    assert!(ColorChoice::Always.enabled(true, no_color));
 }
@@ -0,0 +1,152 @@
+use crate::callable::Callable;
+use crate::expression::LiteralValue;
+use crate::interpreter::{Interpreter, RuntimeError};
+use std::fmt;
+use std::fmt::Formatter;
+#[cfg(test)]
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the Rust side of a native, it is only called with as many arguments as its arity
+pub(crate) type NativeFn =
+    dyn Fn(&mut Interpreter, &[LiteralValue]) -> Result<LiteralValue, RuntimeError>;
+
+// a function implemented in Rust and made available to Lox code as a global
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub(crate) fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut Interpreter, &[LiteralValue]) -> Result<LiteralValue, RuntimeError>
+            + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<LiteralValue>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        (self.function)(interpreter, &arguments)
+    }
+}
+
+// like other functions, natives are only equal to themselves
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+// the natives every interpreter starts with
+pub(crate) fn builtins() -> Vec<NativeFunction> {
+    vec![NativeFunction::new("clock", 0, clock)]
+}
+
+// typed access to the arguments of a native, the errors name the argument by its position
+// and are reported at the call
+
+pub fn number(arguments: &[LiteralValue], index: usize) -> Result<f64, RuntimeError> {
+    match arguments.get(index) {
+        Some(LiteralValue::Number(number)) => Ok(*number),
+        _ => Err(argument_error(index, "a number")),
+    }
+}
+
+pub fn string(arguments: &[LiteralValue], index: usize) -> Result<&str, RuntimeError> {
+    match arguments.get(index) {
+        Some(LiteralValue::String(string)) => Ok(string),
+        _ => Err(argument_error(index, "a string")),
+    }
+}
+
+pub fn boolean(arguments: &[LiteralValue], index: usize) -> Result<bool, RuntimeError> {
+    match arguments.get(index) {
+        Some(LiteralValue::Boolean(boolean)) => Ok(*boolean),
+        _ => Err(argument_error(index, "a boolean")),
+    }
+}
+
+fn argument_error(index: usize, expected: &str) -> RuntimeError {
+    RuntimeError::native(&format!("Argument {} must be {expected}.", index + 1))
+}
+
+fn clock(
+    _interpreter: &mut Interpreter,
+    _arguments: &[LiteralValue],
+) -> Result<LiteralValue, RuntimeError> {
+    Ok(LiteralValue::Number(seconds_since_epoch()))
+}
+
+// what `clock` returns, with sub-second precision
+pub(crate) fn seconds_since_epoch() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[test]
+fn test_clock_counts_seconds_since_the_epoch() {
+    let Ok(LiteralValue::Number(seconds)) = clock(&mut Interpreter::new(), &[]) else {
+        panic!("clock did not return a number");
+    };
+
+    // 2020-01-01, long past, and well before the clock is off by a factor of 1000
+    assert!(seconds > 1_577_836_800.0);
+    assert!(seconds < 1_577_836_800_000.0);
+}
+
+#[test]
+fn test_argument_helpers() {
+    let arguments = [
+        LiteralValue::Number(1.0),
+        LiteralValue::String(Rc::from("a")),
+    ];
+
+    assert_eq!(number(&arguments, 0), Ok(1.0));
+    assert_eq!(string(&arguments, 1), Ok("a"));
+    assert_eq!(
+        boolean(&arguments, 1).map_err(|error| error.message),
+        Err("Argument 2 must be a boolean.".to_string())
+    );
+    assert_eq!(
+        number(&arguments, 2).map_err(|error| error.message),
+        Err("Argument 3 must be a number.".to_string())
+    );
+}
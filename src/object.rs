@@ -0,0 +1,183 @@
+use crate::chunk::Function;
+use crate::diagnostics::paint;
+use colored::Colorize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+// the values of the bytecode backend, cheap to clone: everything beyond numbers and booleans
+// is reference counted
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<str>),
+    Closure(Rc<Closure>),
+    Native(Rc<Native>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    // the way the REPL shows a value, the same as for the tree-walking interpreter
+    pub fn repr(&self, color: bool) -> String {
+        let text = self.to_string();
+        match self {
+            Value::Boolean(_) => paint(&text, color, |text| text.blue()),
+            Value::Nil => paint(&text, color, |text| text.red()),
+            Value::Number(_) => paint(&text, color, |text| text.yellow()),
+            Value::String(_) => paint(&format!("\"{text}\""), color, |text| text.green()),
+            Value::Closure(_)
+            | Value::Native(_)
+            | Value::Class(_)
+            | Value::Instance(_)
+            | Value::BoundMethod(_) => paint(&text, color, |text| text.cyan()),
+        }
+    }
+}
+
+// Lox equality: strings are compared by content, objects are only equal to themselves
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Closure(left), Value::Closure(right)) => Rc::ptr_eq(left, right),
+            (Value::Native(left), Value::Native(right)) => Rc::ptr_eq(left, right),
+            (Value::Class(left), Value::Class(right)) => Rc::ptr_eq(left, right),
+            (Value::Instance(left), Value::Instance(right)) => Rc::ptr_eq(left, right),
+            (Value::BoundMethod(left), Value::BoundMethod(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+// the way `print` shows a value
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::Number(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+            Value::Closure(closure) => write!(f, "{}", closure.function.display()),
+            Value::Native(_) => write!(f, "<native fn>"),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Value::BoundMethod(bound) => write!(f, "{}", bound.method.function.display()),
+        }
+    }
+}
+
+// a variable captured by a closure: it stays on the stack while its scope is alive and
+// is moved into the upvalue when the scope ends
+#[derive(Debug)]
+pub enum Upvalue {
+    // the stack slot of the variable
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// the upvalues are left out, they can (indirectly) contain the closure itself
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("name", &self.function.name)
+            .finish()
+    }
+}
+
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Native").field("name", &self.name).finish()
+    }
+}
+
+pub struct Class {
+    pub name: Rc<str>,
+    // inherited methods are copied in when the class is created
+    pub methods: RefCell<HashMap<Rc<str>, Rc<Closure>>>,
+}
+
+impl Class {
+    pub fn new(name: Rc<str>) -> Self {
+        Self {
+            name,
+            methods: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Class").field("name", &self.name).finish()
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Rc<str>, Value>>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+// the fields are left out, they can (indirectly) contain the instance itself
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("class", &self.class.name)
+            .finish()
+    }
+}
+
+// a method together with the instance it was accessed on
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+#[test]
+fn test_strings_are_equal_by_content() {
+    assert_eq!(Value::String(Rc::from("a")), Value::String(Rc::from("a")));
+    assert_ne!(Value::String(Rc::from("1")), Value::Number(1.0));
+    assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+}
+
+#[test]
+fn test_objects_are_equal_by_identity() {
+    let class = Rc::new(Class::new(Rc::from("Point")));
+    let instance = Value::Instance(Rc::new(Instance::new(Rc::clone(&class))));
+
+    assert_eq!(instance, instance.clone());
+    assert_ne!(instance, Value::Instance(Rc::new(Instance::new(class))));
+    assert_eq!(instance.to_string(), "Point instance");
+}
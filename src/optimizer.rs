@@ -0,0 +1,321 @@
+use crate::expression::{Expr, LiteralValue};
+#[cfg(test)]
+use crate::interpreter::Interpreter;
+use crate::statement::{FunctionDeclaration, Statement};
+#[cfg(test)]
+use crate::test_support::SharedBuffer;
+use crate::token::TokenType;
+#[cfg(test)]
+use crate::vm::Vm;
+#[cfg(test)]
+use std::io;
+#[cfg(test)]
+use std::io::Write;
+use std::rc::Rc;
+
+// Rewrites resolved statements into ones that run the same but do less work:
+//
+// - unary and binary operators over literals are computed once, `2 * 60` becomes `120`;
+//   operations that would fail, like `-"a"`, are left for the runtime error
+// - groupings are removed, they only matter to the parser
+// - `and`/`or` with a literal on the left become the operand they always give
+// - `if`s with a literal condition become the branch that runs, `while (false)` goes away
+//
+// Nothing is moved between scopes, so the depths from the resolver stay valid.
+//
+// `if (true) 1;` becomes `1;`, so whether the source ends with an expression statement has to
+// be decided before optimizing it.
+pub fn optimize(statements: Vec<Statement>) -> Vec<Statement> {
+    optimize_statements(statements)
+}
+
+fn optimize_statements(statements: Vec<Statement>) -> Vec<Statement> {
+    statements
+        .into_iter()
+        .filter_map(optimize_statement)
+        .collect()
+}
+
+// `None` for a statement that does nothing
+fn optimize_statement(statement: Statement) -> Option<Statement> {
+    let optimized = match statement {
+        Statement::Expression(expr, span) => Statement::Expression(optimize_expression(expr), span),
+        Statement::Function(declaration) => Statement::Function(optimize_function(declaration)),
+        Statement::Print(expr, span) => Statement::Print(optimize_expression(expr), span),
+        Statement::Return(keyword, value, span) => {
+            Statement::Return(keyword, value.map(optimize_expression), span)
+        }
+        Statement::Block(statements, span) => {
+            Statement::Block(optimize_statements(statements), span)
+        }
+        Statement::Class(name, superclass, methods, span) => Statement::Class(
+            name,
+            superclass,
+            methods.into_iter().map(optimize_function).collect(),
+            span,
+        ),
+        Statement::If(condition, then_branch, else_branch, span) => {
+            let condition = optimize_expression(condition);
+            if let Expr::Literal(value, _) = &condition {
+                return if value.is_truthy() {
+                    optimize_statement(*then_branch)
+                } else {
+                    else_branch.and_then(|branch| optimize_statement(*branch))
+                };
+            }
+            Statement::If(
+                condition,
+                Box::new(optimize_body(*then_branch)),
+                else_branch.and_then(|branch| optimize_statement(*branch).map(Box::new)),
+                span,
+            )
+        }
+        Statement::Var(name, initializer, span) => {
+            Statement::Var(name, initializer.map(optimize_expression), span)
+        }
+        Statement::While(condition, loop_body, span) => {
+            let condition = optimize_expression(condition);
+            if matches!(&condition, Expr::Literal(value, _) if !value.is_truthy()) {
+                return None;
+            }
+            Statement::While(condition, Box::new(optimize_body(*loop_body)), span)
+        }
+    };
+    Some(optimized)
+}
+
+// the body of an `if` or a loop, which has to be there even when it does nothing
+fn optimize_body(body: Statement) -> Statement {
+    let span = body.span();
+    optimize_statement(body).unwrap_or(Statement::Block(Vec::new(), span))
+}
+
+// the declaration is only shared once the function has been run, which it has not
+fn optimize_function(declaration: Rc<FunctionDeclaration>) -> Rc<FunctionDeclaration> {
+    match Rc::try_unwrap(declaration) {
+        Ok(declaration) => Rc::new(FunctionDeclaration {
+            body: optimize_statements(declaration.body),
+            ..declaration
+        }),
+        Err(declaration) => declaration,
+    }
+}
+
+fn optimize_expression(expr: Expr) -> Expr {
+    match expr {
+        Expr::Assignment(name, value, binding, span) => {
+            Expr::Assignment(name, Box::new(optimize_expression(*value)), binding, span)
+        }
+        Expr::Binary(left, operator, right, span) => {
+            let left = optimize_expression(*left);
+            let right = optimize_expression(*right);
+            if let (Expr::Literal(left, _), Expr::Literal(right, _)) = (&left, &right) {
+                if let Some(value) = binary(left, &operator.token_type, right) {
+                    return Expr::Literal(value, span);
+                }
+            }
+            Expr::Binary(Box::new(left), operator, Box::new(right), span)
+        }
+        Expr::Call(callee, paren, arguments, span) => Expr::Call(
+            Box::new(optimize_expression(*callee)),
+            paren,
+            arguments.into_iter().map(optimize_expression).collect(),
+            span,
+        ),
+        Expr::Get(object, name, span) => {
+            Expr::Get(Box::new(optimize_expression(*object)), name, span)
+        }
+        Expr::Grouping(expr, _) => optimize_expression(*expr),
+        Expr::Logical(left, operator, right, span) => {
+            let left = optimize_expression(*left);
+            let right = optimize_expression(*right);
+            match &left {
+                Expr::Literal(value, _) => {
+                    let decided = match operator.token_type {
+                        TokenType::Or => value.is_truthy(),
+                        _ => !value.is_truthy(),
+                    };
+                    if decided {
+                        left
+                    } else {
+                        right
+                    }
+                }
+                _ => Expr::Logical(Box::new(left), operator, Box::new(right), span),
+            }
+        }
+        Expr::Set(object, name, value, span) => Expr::Set(
+            Box::new(optimize_expression(*object)),
+            name,
+            Box::new(optimize_expression(*value)),
+            span,
+        ),
+        Expr::Unary(operator, operand, span) => {
+            let operand = optimize_expression(*operand);
+            if let Expr::Literal(value, _) = &operand {
+                if let Some(value) = unary(&operator.token_type, value) {
+                    return Expr::Literal(value, span);
+                }
+            }
+            Expr::Unary(operator, Box::new(operand), span)
+        }
+        expr @ (Expr::Literal(..) | Expr::Super(..) | Expr::This(..) | Expr::Variable(..)) => expr,
+    }
+}
+
+// the value of the operator, or `None` when applying it is a runtime error
+fn unary(operator: &TokenType, operand: &LiteralValue) -> Option<LiteralValue> {
+    match (operator, operand) {
+        (TokenType::Bang, _) => Some(LiteralValue::Boolean(!operand.is_truthy())),
+        (TokenType::Minus, LiteralValue::Number(number)) => Some(LiteralValue::Number(-number)),
+        _ => None,
+    }
+}
+
+fn binary(left: &LiteralValue, operator: &TokenType, right: &LiteralValue) -> Option<LiteralValue> {
+    let value = match (left, operator, right) {
+        (_, TokenType::EqualEqual, _) => LiteralValue::Boolean(left == right),
+        (_, TokenType::BangEqual, _) => LiteralValue::Boolean(left != right),
+        (LiteralValue::Number(left), operator, LiteralValue::Number(right)) => match operator {
+            TokenType::Plus => LiteralValue::Number(left + right),
+            TokenType::Minus => LiteralValue::Number(left - right),
+            TokenType::Slash => LiteralValue::Number(left / right),
+            TokenType::Star => LiteralValue::Number(left * right),
+            TokenType::Greater => LiteralValue::Boolean(left > right),
+            TokenType::GreaterEqual => LiteralValue::Boolean(left >= right),
+            TokenType::Less => LiteralValue::Boolean(left < right),
+            TokenType::LessEqual => LiteralValue::Boolean(left <= right),
+            _ => return None,
+        },
+        (LiteralValue::String(left), TokenType::Plus, LiteralValue::String(right)) => {
+            LiteralValue::String(Rc::from(format!("{left}{right}")))
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+fn optimized(source: &str) -> Vec<Statement> {
+    optimize(crate::parse(source).expect("Parsing failed"))
+}
+
+// the value printed by the only statement of `source`, which has to be a `print`
+#[cfg(test)]
+fn printed(source: &str) -> Expr {
+    match optimized(source).pop() {
+        Some(Statement::Print(expr, _)) => expr,
+        statement => panic!("{statement:?} is not a print statement"),
+    }
+}
+
+// what running `source` on both backends prints, followed by the error if there is one
+#[cfg(test)]
+fn outputs(source: &str, optimize: bool) -> (String, String) {
+    let mut buffer = SharedBuffer::default();
+    let mut interpreter = Interpreter::with_output(buffer.clone(), io::sink());
+    interpreter.set_optimize(optimize);
+    if let Err(error) = interpreter.run(source) {
+        writeln!(buffer, "{error}").unwrap();
+    }
+    let tree = buffer.take();
+
+    let mut vm = Vm::with_output(buffer.clone(), io::sink());
+    vm.set_optimize(optimize);
+    if let Err(error) = vm.run(source) {
+        writeln!(buffer, "{error}").unwrap();
+    }
+    let bytecode = buffer.take();
+
+    (tree, bytecode)
+}
+
+#[test]
+fn test_operators_over_literals_are_folded() {
+    let literal = |source| match printed(source) {
+        Expr::Literal(value, _) => value,
+        expr => panic!("{expr:?} is not a literal"),
+    };
+
+    assert_eq!(literal("print 2 * 60 * 60;"), LiteralValue::Number(7200.0));
+    assert_eq!(
+        literal("print \"a\" + \"b\";"),
+        LiteralValue::String(Rc::from("ab"))
+    );
+    assert_eq!(literal("print -(1 + 2);"), LiteralValue::Number(-3.0));
+    assert_eq!(literal("print !(1 < 2);"), LiteralValue::Boolean(false));
+    assert_eq!(literal("print nil == false;"), LiteralValue::Boolean(false));
+    assert_eq!(
+        literal("print false or \"b\";"),
+        LiteralValue::String(Rc::from("b"))
+    );
+}
+
+#[test]
+fn test_failing_operations_are_left_for_runtime() {
+    assert!(matches!(printed("print -\"a\";"), Expr::Unary(..)));
+    assert!(matches!(printed("print 1 + \"a\";"), Expr::Binary(..)));
+    assert!(matches!(printed("print 1 < nil;"), Expr::Binary(..)));
+}
+
+#[test]
+fn test_groupings_are_removed() {
+    match printed("var a = 1; print ((a) + (2));") {
+        Expr::Binary(left, _, right, _) => {
+            assert!(matches!(*left, Expr::Variable(..)));
+            assert!(matches!(*right, Expr::Literal(..)));
+        }
+        expr => panic!("{expr:?} is not a binary expression"),
+    }
+}
+
+#[test]
+fn test_dead_branches_are_removed() {
+    assert!(optimized("if (1 > 2) print 1; while (false) print 2;").is_empty());
+    assert!(matches!(
+        optimized("if (nil) print 1; else print 2;").as_slice(),
+        [Statement::Print(
+            Expr::Literal(LiteralValue::Number(2.0), _),
+            _
+        )]
+    ));
+    assert!(matches!(
+        optimized("var a; if (a) print 1; else if (false) print 2;").as_slice(),
+        [Statement::Var(..), Statement::If(_, _, None, _)]
+    ));
+}
+
+#[test]
+fn test_no_final_expression_is_introduced() {
+    assert!(matches!(
+        optimized("if (true) 1;").as_slice(),
+        [Statement::Expression(..)]
+    ));
+    assert_eq!(Interpreter::new().run("if (true) 1;"), Ok(None));
+    assert_eq!(Vm::new().run("if (true) 1;"), Ok(None));
+    assert_eq!(
+        Interpreter::new().run("1 + 2;"),
+        Ok(Some(LiteralValue::Number(3.0)))
+    );
+}
+
+#[test]
+fn test_output_is_the_same_with_and_without_optimizations() {
+    let programs = [
+        "print 2 * 60 * 60; print \"a\" + \"b\"; print 1 / 0; print -(0);",
+        "print 1 == 1.0; print \"1\" == 1; print !nil; print (1 + 2) * 3 - 4 / 8;",
+        "var a = 1; { var a = 2; if (false) { var b = a; print b; } print a; } print a;",
+        "fun f(n) { if (true) return n * (2 + 3); return 0; } print f(4);",
+        "fun f() { var x = 1; fun g() { if (false) x = 2; return x; } return g; } print f()();",
+        "var i = 0; while (false) i = i + 1; while (i < 3) { if (!false) i = i + 1; } print i;",
+        "print nil or \"default\"; print true and (1 + 1); print false and undefined;",
+        "class A { m() { return 10 * 10; } } class B < A { m() { return super.m() + -1; } }
+        print B().m();",
+        "print \"before\"; print -\"a\";",
+        "print 1; if (true) print 2 + nil;",
+    ];
+
+    for program in programs {
+        assert_eq!(outputs(program, true), outputs(program, false), "{program}");
+    }
+}
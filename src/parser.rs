@@ -1,12 +1,47 @@
-use crate::expression::{Expr, LiteralValue};
-use crate::statement::Statement;
-use crate::token::{Token, TokenType};
+use crate::expression::{Binding, Expr, LiteralValue};
+use crate::interner::intern;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Span, Token, TokenType};
+use std::fmt;
+use std::fmt::Formatter;
 use std::iter::Peekable;
+use std::mem;
+use std::rc::Rc;
 use std::slice::Iter;
 
+const MAX_ARGUMENTS: usize = 255;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.token.token_type {
+            TokenType::Eof => write!(
+                f,
+                "[line {}] Error at end: {}",
+                self.token.line, self.message
+            ),
+            _ => write!(
+                f,
+                "[line {}] Error at '{}': {}",
+                self.token.line, self.token.lexeme, self.message
+            ),
+        }
+    }
+}
+
 pub struct Parser<'a> {
     pub tokens: Peekable<Iter<'a, Token>>,
     prev_token: Option<&'a Token>,
+    errors: Vec<ParseError>,
+    // errors at the end are reported at it when the tokens do not end with an end of file token
+    end_of_file: Token,
 }
 
 impl<'a> Parser<'a> {
@@ -14,16 +49,30 @@ impl<'a> Parser<'a> {
         Parser {
             tokens: tokens.iter().peekable(),
             prev_token: None,
+            errors: Vec::new(),
+            end_of_file: Token {
+                lexeme: intern(""),
+                token_type: TokenType::Eof,
+                line: 0,
+                span: Span::default(),
+            },
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Statement> {
+    // parses the whole program, reporting every syntax error instead of stopping at the first
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<ParseError>> {
         let mut statements = Vec::new();
         while !self.at_end() {
-            statements.push(self.declaration());
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
         }
 
-        statements
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(mem::take(&mut self.errors))
+        }
     }
 
     fn at_end(&mut self) -> bool {
@@ -34,134 +83,393 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn declaration(&mut self) -> Statement {
-        if let Some(token) = self.tokens.peek() {
+    // a failed declaration is recorded and skipped, so that parsing can go on
+    fn declaration(&mut self) -> Option<Statement> {
+        match self.try_declaration() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn try_declaration(&mut self) -> Result<Statement, ParseError> {
+        match self.peek_type() {
+            Some(TokenType::Var) => {
+                self.advance();
+                self.var_declaration()
+            }
+            Some(TokenType::Fun) => {
+                self.advance();
+                let keyword_span = self.previous_span();
+                Ok(Statement::Function(
+                    self.function("function", Some(keyword_span))?,
+                ))
+            }
+            Some(TokenType::Class) => {
+                self.advance();
+                self.class_declaration()
+            }
+            _ => self.statement(),
+        }
+    }
+
+    // discards tokens until the start of the next statement
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while let Some(token) = self.tokens.peek() {
+            if self
+                .prev_token
+                .is_some_and(|prev| prev.token_type == TokenType::Semicolon)
+            {
+                return;
+            }
+
             match token.token_type {
-                TokenType::Var => {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Eof => return,
+                _ => {
                     self.advance();
-                    self.var_declaration()
                 }
-                _ => self.statement(),
             }
-        } else {
-            panic!("Internal error");
         }
     }
 
-    fn var_declaration(&mut self) -> Statement {
-        let token = *self.tokens.peek().unwrap();
+    fn class_declaration(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
+        let name = self.consume_identifier("Expect class name.")?;
 
-        match token.token_type {
-            TokenType::Identifier(_) => {
-                self.advance();
+        let superclass = if self.check(TokenType::Less) {
+            self.advance();
+            let superclass = self.consume_identifier("Expect superclass name.")?;
+            if superclass.lexeme == name.lexeme {
+                self.error(Some(superclass), "A class can't inherit from itself.");
+            }
+            Some(Expr::Variable(
+                superclass.clone(),
+                Binding::default(),
+                superclass.span,
+            ))
+        } else {
+            None
+        };
 
-                let next_token = self.tokens.peek().unwrap();
-                let mut initializer = None;
-                if next_token.token_type == TokenType::Equal {
-                    self.advance();
-                    initializer = Some(self.expression());
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.at_end() {
+            methods.push(self.function("method", None)?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Statement::Class(
+            name.clone(),
+            superclass,
+            methods,
+            self.span_from(start),
+        ))
+    }
+
+    // parses a function or a method, `kind` is only used in error messages;
+    // a function's span starts at its `fun` keyword, a method's at its name
+    fn function(
+        &mut self,
+        kind: &str,
+        keyword_span: Option<Span>,
+    ) -> Result<Rc<FunctionDeclaration>, ParseError> {
+        let name = self.consume_identifier(&format!("Expect {kind} name."))?;
+        let start = keyword_span.unwrap_or(name.span);
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {kind} name."),
+        )?;
+
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let token = self.current_token();
+                    self.error(
+                        token,
+                        &format!("Can't have more than {MAX_ARGUMENTS} parameters."),
+                    );
                 }
-                let next_token = *self.tokens.peek().unwrap();
-                if next_token.token_type != TokenType::Semicolon {
-                    panic!("Missing ; after var declaration!");
+                params.push(self.consume_identifier("Expect parameter name.")?.clone());
+
+                if !self.check(TokenType::Comma) {
+                    break;
                 }
                 self.advance();
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {kind} body."),
+        )?;
+
+        let body = self.block()?;
 
-                Statement::Var(token.clone(), initializer)
+        Ok(Rc::new(FunctionDeclaration {
+            name: name.clone(),
+            params,
+            body,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn var_declaration(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
+        let token = self.consume_identifier("Expect variable name.")?;
+
+        let mut initializer = None;
+        if self.check(TokenType::Equal) {
+            self.advance();
+            initializer = Some(self.expression()?);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+
+        Ok(Statement::Var(
+            token.clone(),
+            initializer,
+            self.span_from(start),
+        ))
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        match self.peek_type() {
+            Some(TokenType::Print) => {
+                self.advance();
+                let start = self.previous_span();
+                let expr = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+
+                Ok(Statement::Print(expr, self.span_from(start)))
+            }
+            Some(TokenType::If) => {
+                self.advance();
+                self.if_statement()
             }
-            _ => {
-                panic!("Expected variable name after var!");
+            Some(TokenType::While) => {
+                self.advance();
+                self.while_statement()
+            }
+            Some(TokenType::For) => {
+                self.advance();
+                self.for_statement()
+            }
+            Some(TokenType::Return) => {
+                self.advance();
+                self.return_statement()
             }
+            Some(TokenType::LeftBrace) => {
+                self.advance();
+                let start = self.previous_span();
+                let statements = self.block()?;
+
+                Ok(Statement::Block(statements, self.span_from(start)))
+            }
+            _ => self.expression_statement(),
         }
     }
 
-    fn statement(&mut self) -> Statement {
-        if let Some(token) = self.tokens.peek() {
-            match token.token_type {
-                TokenType::Print => {
-                    self.advance();
-                    let expr = self.expression();
-
-                    if let Some(token) = self.tokens.peek() {
-                        match token.token_type {
-                            TokenType::Semicolon => {
-                                self.advance();
-                                Statement::Print(expr)
-                            }
-                            _ => {
-                                panic!("Missing semicolon on line {}!", token.line);
-                            }
-                        }
-                    } else {
-                        panic!("Missing expression!");
-                    }
-                }
-                TokenType::LeftBrace => {
-                    let mut statements = Vec::new();
-                    self.advance();
+    // parses the rest of a block after its opening brace
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = Vec::new();
 
-                    while let Some(token) = self.tokens.peek() {
-                        match token.token_type {
-                            TokenType::RightBrace => {
-                                self.advance();
-                                break;
-                            }
-                            _ => {
-                                statements.push(self.declaration());
-                            }
-                        }
-                    }
-                    Statement::Block(statements)
-                }
-                _ => {
-                    let expr = self.expression();
-
-                    if let Some(token) = self.tokens.peek() {
-                        match token.token_type {
-                            TokenType::Semicolon => {
-                                self.advance();
-                                Statement::Expression(expr)
-                            }
-                            _ => {
-                                panic!("Missing semicolon on line {}!", token.line);
-                            }
-                        }
-                    } else {
-                        panic!("Missing expression!");
-                    }
-                }
+        while !self.check(TokenType::RightBrace) && !self.at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
             }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+
+        Ok(statements)
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, ParseError> {
+        let keyword = self.prev_token.unwrap().clone();
+        let value = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+
+        let span = self.span_from(keyword.span);
+
+        Ok(Statement::Return(keyword, value, span))
+    }
+
+    fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let expr = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        let span = self.span_from(expr.span());
+
+        Ok(Statement::Expression(expr, span))
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.check(TokenType::Else) {
+            self.advance();
+            Some(Box::new(self.statement()?))
         } else {
-            panic!("Internal error");
+            None
+        };
+
+        Ok(Statement::If(
+            condition,
+            then_branch,
+            else_branch,
+            self.span_from(start),
+        ))
+    }
+
+    fn while_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.statement()?;
+
+        Ok(Statement::While(
+            condition,
+            Box::new(body),
+            self.span_from(start),
+        ))
+    }
+
+    // `for` has no runtime node of its own, it is desugared into a block with a while loop:
+    // { initializer; while (condition) { body; increment; } }
+    // the made up nodes all get the span of the whole `for` statement
+    fn for_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.previous_span();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.check(TokenType::Semicolon) {
+            self.advance();
+            None
+        } else if self.check(TokenType::Var) {
+            self.advance();
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::Semicolon) {
+            let span = self.current_token().map(|token| token.span);
+            Expr::Literal(LiteralValue::Boolean(true), span.unwrap_or_default())
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+        let span = self.span_from(start);
+        if let Some(increment) = increment {
+            let increment_span = increment.span();
+            body = Statement::Block(
+                vec![body, Statement::Expression(increment, increment_span)],
+                span,
+            );
+        }
+        body = Statement::While(condition, Box::new(body), span);
+        if let Some(initializer) = initializer {
+            body = Statement::Block(vec![initializer, body], span);
         }
+
+        Ok(body)
     }
 
-    fn expression(&mut self) -> Expr {
+    fn expression(&mut self) -> Result<Expr, ParseError> {
         self.assignment()
     }
 
-    fn assignment(&mut self) -> Expr {
-        let mut expr = self.equality();
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.or()?;
 
-        while let Some(token) = self.tokens.peek() {
-            match token.token_type {
-                TokenType::Equal => {
-                    self.advance();
+        if self.check(TokenType::Equal) {
+            self.advance();
+            let equals = self.prev_token.unwrap();
+            let value = Box::new(self.assignment()?);
+            let span = expr.span().to(value.span());
 
-                    let value = self.assignment();
-                    if let Expr::Variable(token) = expr {
-                        expr = Expr::Assignment(token, Box::new(value));
-                    }
+            return match expr {
+                Expr::Variable(token, binding, _) => {
+                    Ok(Expr::Assignment(token, value, binding, span))
                 }
-                _ => break,
-            }
+                Expr::Get(object, name, _) => Ok(Expr::Set(object, name, value, span)),
+                _ => {
+                    // reported, but the parser is not confused, so there is no need to synchronize
+                    self.error(Some(equals), "Invalid assignment target.");
+                    Ok(expr)
+                }
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+
+        while self.check(TokenType::Or) {
+            self.advance();
+
+            let op = self.prev_token.unwrap();
+            let right = self.and()?;
+
+            let span = expr.span().to(right.span());
+            expr = Expr::Logical(Box::new(expr), op.clone(), Box::new(right), span);
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.equality()?;
+
+        while self.check(TokenType::And) {
+            self.advance();
+
+            let op = self.prev_token.unwrap();
+            let right = self.equality()?;
+
+            let span = expr.span().to(right.span());
+            expr = Expr::Logical(Box::new(expr), op.clone(), Box::new(right), span);
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn equality(&mut self) -> Expr {
-        let mut expr = self.comparison();
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.comparison()?;
 
         while let Some(token) = self.tokens.peek() {
             match token.token_type {
@@ -169,19 +477,20 @@ impl<'a> Parser<'a> {
                     self.advance();
 
                     let op = self.prev_token.unwrap();
-                    let right = self.comparison();
+                    let right = self.comparison()?;
 
-                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right));
+                    let span = expr.span().to(right.span());
+                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right), span);
                 }
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Expr {
-        let mut expr = self.term();
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
 
         while let Some(token) = self.tokens.peek() {
             match token.token_type {
@@ -192,19 +501,20 @@ impl<'a> Parser<'a> {
                     self.advance();
 
                     let op = self.prev_token.unwrap();
-                    let right = self.term();
+                    let right = self.term()?;
 
-                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right));
+                    let span = expr.span().to(right.span());
+                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right), span);
                 }
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn term(&mut self) -> Expr {
-        let mut expr = self.factor();
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.factor()?;
 
         while let Some(token) = self.tokens.peek() {
             match token.token_type {
@@ -212,101 +522,139 @@ impl<'a> Parser<'a> {
                     self.advance();
 
                     let op = self.prev_token.unwrap();
-                    let right = self.factor();
+                    let right = self.factor()?;
 
-                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right));
+                    let span = expr.span().to(right.span());
+                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right), span);
                 }
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn factor(&mut self) -> Expr {
-        let mut expr = self.unary();
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
 
         while let Some(token) = self.tokens.peek() {
             match token.token_type {
                 TokenType::Slash | TokenType::Star => {
                     self.advance();
                     let op = self.prev_token.unwrap();
-                    let right = self.unary();
+                    let right = self.unary()?;
 
-                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right));
+                    let span = expr.span().to(right.span());
+                    expr = Expr::Binary(Box::new(expr), op.clone(), Box::new(right), span);
                 }
                 _ => break,
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Expr {
-        match self.tokens.peek().unwrap().token_type {
-            TokenType::Bang | TokenType::Minus => {
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        match self.peek_type() {
+            Some(TokenType::Bang | TokenType::Minus) => {
                 self.advance();
 
                 let op = self.prev_token.unwrap();
-                let right = self.unary();
-                Expr::Unary(op.clone(), Box::new(right))
+                let right = self.unary()?;
+                let span = op.span.to(right.span());
+                Ok(Expr::Unary(op.clone(), Box::new(right), span))
             }
-            _ => self.primary(),
+            _ => self.call(),
         }
     }
 
-    fn primary(&mut self) -> Expr {
-        let token = self.tokens.peek().unwrap();
-        match &token.token_type {
-            TokenType::False => {
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.check(TokenType::LeftParen) {
                 self.advance();
-                Expr::Literal(LiteralValue::Boolean(false))
-            }
-            TokenType::True => {
+                expr = self.finish_call(expr)?;
+            } else if self.check(TokenType::Period) {
                 self.advance();
-                Expr::Literal(LiteralValue::Boolean(true))
+                let name = self.consume_identifier("Expect property name after '.'.")?;
+                let span = expr.span().to(name.span);
+                expr = Expr::Get(Box::new(expr), name.clone(), span);
+            } else {
+                break;
             }
-            TokenType::Nil => {
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let token = self.current_token();
+                    self.error(
+                        token,
+                        &format!("Can't have more than {MAX_ARGUMENTS} arguments."),
+                    );
+                }
+                arguments.push(self.expression()?);
+
+                if !self.check(TokenType::Comma) {
+                    break;
+                }
                 self.advance();
-                Expr::Literal(LiteralValue::Nil)
             }
-            TokenType::Number(n) => {
-                self.advance();
-                Expr::Literal(LiteralValue::Number(*n))
+        }
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+
+        let span = callee.span().to(paren.span);
+
+        Ok(Expr::Call(Box::new(callee), paren.clone(), arguments, span))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let Some(token) = self.current_token() else {
+            return Err(self.make_error(None, "Expect expression."));
+        };
+        let expr = match &token.token_type {
+            TokenType::False => Expr::Literal(LiteralValue::Boolean(false), token.span),
+            TokenType::True => Expr::Literal(LiteralValue::Boolean(true), token.span),
+            TokenType::Nil => Expr::Literal(LiteralValue::Nil, token.span),
+            TokenType::Number(n) => Expr::Literal(LiteralValue::Number(*n), token.span),
+            TokenType::StringLiteral(s) => {
+                Expr::Literal(LiteralValue::String(s.clone()), token.span)
             }
             TokenType::Identifier(_) => {
-                self.advance();
-                Expr::Variable(self.prev_token.unwrap().clone())
+                Expr::Variable(token.clone(), Binding::default(), token.span)
             }
-            TokenType::StringLiteral(s) => {
+            TokenType::This => Expr::This(token.clone(), Binding::default(), token.span),
+            TokenType::Super => {
                 self.advance();
-                Expr::Literal(LiteralValue::String(s.clone()))
+                self.consume(TokenType::Period, "Expect '.' after 'super'.")?;
+                let method = self.consume_identifier("Expect superclass method name.")?;
+
+                return Ok(Expr::Super(
+                    token.clone(),
+                    method.clone(),
+                    Binding::default(),
+                    token.span.to(method.span),
+                ));
             }
             TokenType::LeftParen => {
                 self.advance();
-                let expr = self.expression();
-
-                if let Some(token) = self.tokens.peek() {
-                    match token.token_type {
-                        TokenType::RightParen => {
-                            self.advance();
-                            Expr::Grouping(Box::new(expr))
-                        }
-                        _ => {
-                            panic!("Missing closing parenthesis!");
-                        }
-                    }
-                } else {
-                    panic!("Missing closing parenthesis!");
-                }
-            }
-            token_type => {
-                panic!(
-                    "Syntax error! Encountered token: {:?} at line: {}",
-                    token_type, token.line
-                );
+                let expr = self.expression()?;
+                self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
+
+                return Ok(Expr::Grouping(Box::new(expr), self.span_from(token.span)));
             }
-        }
+            _ => return Err(self.make_error(Some(token), "Expect expression.")),
+        };
+        self.advance();
+
+        Ok(expr)
     }
 
     fn advance(&mut self) -> Option<&Token> {
@@ -314,260 +662,821 @@ impl<'a> Parser<'a> {
 
         self.prev_token
     }
+
+    // the span of the last consumed token
+    fn previous_span(&self) -> Span {
+        self.prev_token.map(|token| token.span).unwrap_or_default()
+    }
+
+    // the span from `start` up to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
+    }
+
+    fn peek_type(&mut self) -> Option<&TokenType> {
+        self.tokens.peek().map(|token| &token.token_type)
+    }
+
+    fn check(&mut self, token_type: TokenType) -> bool {
+        self.tokens
+            .peek()
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
+    // the token about to be consumed, `None` if the tokens ran out
+    fn current_token(&mut self) -> Option<&'a Token> {
+        self.tokens.peek().copied()
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<&'a Token, ParseError> {
+        match self.current_token() {
+            Some(token) if matches!(token.token_type, TokenType::Identifier(_)) => {
+                self.advance();
+                Ok(token)
+            }
+            token => Err(self.make_error(token, message)),
+        }
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&'a Token, ParseError> {
+        match self.current_token() {
+            Some(token) if token.token_type == token_type => {
+                self.advance();
+                Ok(token)
+            }
+            token => Err(self.make_error(token, message)),
+        }
+    }
+
+    // an error at `token`, or at the end when there is none
+    fn make_error(&self, token: Option<&Token>, message: &str) -> ParseError {
+        ParseError {
+            token: token.unwrap_or(&self.end_of_file).clone(),
+            message: message.to_string(),
+        }
+    }
+
+    // records an error that does not need the parser to synchronize
+    fn error(&mut self, token: Option<&Token>, message: &str) {
+        let error = self.make_error(token, message);
+        self.errors.push(error);
+    }
+}
+
+// the span from the start of `tokens[first]` to the end of `tokens[last]`
+#[cfg(test)]
+fn span(tokens: &[Token], first: usize, last: usize) -> Span {
+    tokens[first].span.to(tokens[last].span)
+}
+
+#[cfg(test)]
+fn error_messages(tokens: &[Token]) -> Vec<String> {
+    match Parser::init(tokens).parse() {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+    }
 }
 
 #[test]
 fn test_binary() {
-    let tokens = vec![
+    let tokens = [
         Token {
             line: 1,
-            lexeme: String::from("2.0"),
+            lexeme: Rc::from("2.0"),
             token_type: TokenType::Number(2.0),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("+"),
+            lexeme: Rc::from("+"),
             token_type: TokenType::Plus,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("3.0"),
+            lexeme: Rc::from("3.0"),
             token_type: TokenType::Number(3.0),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
+            span: Span::default(),
         },
     ];
-    let expected = vec![Statement::Expression(Expr::Binary(
-        Box::new(Expr::Literal(LiteralValue::Number(2.0))),
-        Token {
-            line: 1,
-            lexeme: String::from("+"),
-            token_type: TokenType::Plus,
-        },
-        Box::new(Expr::Literal(LiteralValue::Number(3.0))),
-    ))];
+    let expected = vec![Statement::Expression(
+        Expr::Binary(
+            Box::new(Expr::Literal(LiteralValue::Number(2.0), Span::default())),
+            Token {
+                line: 1,
+                lexeme: Rc::from("+"),
+                token_type: TokenType::Plus,
+                span: Span::default(),
+            },
+            Box::new(Expr::Literal(LiteralValue::Number(3.0), Span::default())),
+            Span::default(),
+        ),
+        Span::default(),
+    )];
 
     let mut parser = Parser::init(&tokens[..]);
-    assert_eq!(expected, parser.parse());
+    assert_eq!(Ok(expected), parser.parse());
 }
 
 #[test]
 fn test_nested_binary() {
-    let tokens = vec![
+    let tokens = [
         Token {
-            lexeme: String::from("2"),
+            lexeme: Rc::from("2"),
             token_type: TokenType::Number(2.0),
             line: 1,
+            span: Span::default(),
         },
         Token {
-            lexeme: String::from("/"),
+            lexeme: Rc::from("/"),
             token_type: TokenType::Slash,
             line: 1,
+            span: Span::default(),
         },
         Token {
-            lexeme: String::from("2"),
+            lexeme: Rc::from("2"),
             token_type: TokenType::Number(5.0),
             line: 1,
+            span: Span::default(),
         },
         Token {
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
             line: 1,
+            span: Span::default(),
         },
         Token {
-            lexeme: String::from(""),
+            lexeme: Rc::from(""),
             token_type: TokenType::Eof,
             line: 2,
+            span: Span::default(),
         },
     ];
 
-    let expected = vec![Statement::Expression(Expr::Binary(
-        Box::new(Expr::Literal(LiteralValue::Number(2.0))),
-        Token {
-            lexeme: String::from("/"),
-            token_type: TokenType::Slash,
-            line: 1,
-        },
-        Box::new(Expr::Literal(LiteralValue::Number(5.0))),
-    ))];
+    let expected = vec![Statement::Expression(
+        Expr::Binary(
+            Box::new(Expr::Literal(LiteralValue::Number(2.0), Span::default())),
+            Token {
+                lexeme: Rc::from("/"),
+                token_type: TokenType::Slash,
+                line: 1,
+                span: Span::default(),
+            },
+            Box::new(Expr::Literal(LiteralValue::Number(5.0), Span::default())),
+            Span::default(),
+        ),
+        Span::default(),
+    )];
 
     let mut parser = Parser::init(&tokens[..]);
-    assert_eq!(expected, parser.parse());
+    assert_eq!(Ok(expected), parser.parse());
 }
 
 #[test]
 fn test_unary() {
-    let tokens = vec![
+    let tokens = [
         Token {
             line: 1,
-            lexeme: String::from("-"),
+            lexeme: Rc::from("-"),
             token_type: TokenType::Minus,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("10.0"),
+            lexeme: Rc::from("10.0"),
             token_type: TokenType::Number(10.0),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
+            span: Span::default(),
         },
     ];
 
-    let expected = vec![Statement::Expression(Expr::Unary(
-        Token {
-            line: 1,
-            lexeme: String::from("-"),
-            token_type: TokenType::Minus,
-        },
-        Box::new(Expr::Literal(LiteralValue::Number(10.0))),
-    ))];
+    let expected = vec![Statement::Expression(
+        Expr::Unary(
+            Token {
+                line: 1,
+                lexeme: Rc::from("-"),
+                token_type: TokenType::Minus,
+                span: Span::default(),
+            },
+            Box::new(Expr::Literal(LiteralValue::Number(10.0), Span::default())),
+            Span::default(),
+        ),
+        Span::default(),
+    )];
 
     let mut parser = Parser::init(&tokens[..]);
-    assert_eq!(expected, parser.parse());
+    assert_eq!(Ok(expected), parser.parse());
 }
 
 #[test]
 fn test_nested_unary() {
-    let tokens = vec![
+    let tokens = [
         Token {
             line: 1,
-            lexeme: String::from("-"),
+            lexeme: Rc::from("-"),
             token_type: TokenType::Minus,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("-"),
+            lexeme: Rc::from("-"),
             token_type: TokenType::Minus,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("10.0"),
+            lexeme: Rc::from("10.0"),
             token_type: TokenType::Number(10.0),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
+            span: Span::default(),
         },
     ];
 
-    let expected = vec![Statement::Expression(Expr::Unary(
-        Token {
-            line: 1,
-            lexeme: String::from("-"),
-            token_type: TokenType::Minus,
-        },
-        Box::new(Expr::Unary(
+    let expected = vec![Statement::Expression(
+        Expr::Unary(
             Token {
                 line: 1,
-                lexeme: String::from("-"),
+                lexeme: Rc::from("-"),
                 token_type: TokenType::Minus,
+                span: Span::default(),
             },
-            Box::new(Expr::Literal(LiteralValue::Number(10.0))),
-        )),
-    ))];
+            Box::new(Expr::Unary(
+                Token {
+                    line: 1,
+                    lexeme: Rc::from("-"),
+                    token_type: TokenType::Minus,
+                    span: Span::default(),
+                },
+                Box::new(Expr::Literal(LiteralValue::Number(10.0), Span::default())),
+                Span::default(),
+            )),
+            Span::default(),
+        ),
+        Span::default(),
+    )];
 
     let mut parser = Parser::init(&tokens[..]);
-    assert_eq!(expected, parser.parse());
+    assert_eq!(Ok(expected), parser.parse());
 }
 
 #[test]
 fn test_var_declaration_with_initialization() {
-    let tokens = vec![
+    let tokens = [
         Token {
             line: 1,
-            lexeme: String::from("var"),
+            lexeme: Rc::from("var"),
             token_type: TokenType::Var,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("greeting"),
-            token_type: TokenType::Identifier(String::from("greeting")),
+            lexeme: Rc::from("greeting"),
+            token_type: TokenType::Identifier(Rc::from("greeting")),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("="),
+            lexeme: Rc::from("="),
             token_type: TokenType::Equal,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("hello!"),
-            token_type: TokenType::StringLiteral(String::from("hello!")),
+            lexeme: Rc::from("hello!"),
+            token_type: TokenType::StringLiteral(Rc::from("hello!")),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
+            span: Span::default(),
         },
     ];
 
     let expected = vec![Statement::Var(
         Token {
             line: 1,
-            lexeme: String::from("greeting"),
-            token_type: TokenType::Identifier(String::from("greeting")),
+            lexeme: Rc::from("greeting"),
+            token_type: TokenType::Identifier(Rc::from("greeting")),
+            span: Span::default(),
         },
-        Some(Expr::Literal(LiteralValue::String(String::from("hello!")))),
+        Some(Expr::Literal(
+            LiteralValue::String(Rc::from("hello!")),
+            Span::default(),
+        )),
+        Span::default(),
     )];
 
     let mut parser = Parser::init(&tokens[..]);
-    assert_eq!(expected, parser.parse());
+    assert_eq!(Ok(expected), parser.parse());
 }
 
 #[test]
 fn test_block_definition() {
-    let tokens = vec![
+    let tokens = [
         Token {
             line: 1,
-            lexeme: String::from("{"),
+            lexeme: Rc::from("{"),
             token_type: TokenType::LeftBrace,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("var"),
+            lexeme: Rc::from("var"),
             token_type: TokenType::Var,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("greeting"),
-            token_type: TokenType::Identifier(String::from("greeting")),
+            lexeme: Rc::from("greeting"),
+            token_type: TokenType::Identifier(Rc::from("greeting")),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("="),
+            lexeme: Rc::from("="),
             token_type: TokenType::Equal,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("hello!"),
-            token_type: TokenType::StringLiteral(String::from("hello!")),
+            lexeme: Rc::from("hello!"),
+            token_type: TokenType::StringLiteral(Rc::from("hello!")),
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
+            span: Span::default(),
         },
         Token {
             line: 1,
-            lexeme: String::from("}"),
+            lexeme: Rc::from("}"),
             token_type: TokenType::RightBrace,
+            span: Span::default(),
         },
     ];
 
-    let expected = vec![Statement::Block(vec![Statement::Var(
-        Token {
-            line: 1,
-            lexeme: String::from("greeting"),
-            token_type: TokenType::Identifier(String::from("greeting")),
-        },
-        Some(Expr::Literal(LiteralValue::String(String::from("hello!")))),
-    )])];
+    let expected = vec![Statement::Block(
+        vec![Statement::Var(
+            Token {
+                line: 1,
+                lexeme: Rc::from("greeting"),
+                token_type: TokenType::Identifier(Rc::from("greeting")),
+                span: Span::default(),
+            },
+            Some(Expr::Literal(
+                LiteralValue::String(Rc::from("hello!")),
+                Span::default(),
+            )),
+            Span::default(),
+        )],
+        Span::default(),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_if_else_statement() {
+    let (tokens, _) = Scanner::init("if (true) print 1; else print 2;").scan_tokens();
+
+    let expected = vec![Statement::If(
+        Expr::Literal(LiteralValue::Boolean(true), tokens[2].span),
+        Box::new(Statement::Print(
+            Expr::Literal(LiteralValue::Number(1.0), tokens[5].span),
+            span(&tokens, 4, 6),
+        )),
+        Some(Box::new(Statement::Print(
+            Expr::Literal(LiteralValue::Number(2.0), tokens[9].span),
+            span(&tokens, 8, 10),
+        ))),
+        span(&tokens, 0, 10),
+    )];
 
     let mut parser = Parser::init(&tokens[..]);
-    assert_eq!(expected, parser.parse());
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_dangling_else_binds_to_nearest_if() {
+    let (tokens, _) = Scanner::init("if (true) if (false) print 1; else print 2;").scan_tokens();
+
+    let expected = vec![Statement::If(
+        Expr::Literal(LiteralValue::Boolean(true), tokens[2].span),
+        Box::new(Statement::If(
+            Expr::Literal(LiteralValue::Boolean(false), tokens[6].span),
+            Box::new(Statement::Print(
+                Expr::Literal(LiteralValue::Number(1.0), tokens[9].span),
+                span(&tokens, 8, 10),
+            )),
+            Some(Box::new(Statement::Print(
+                Expr::Literal(LiteralValue::Number(2.0), tokens[13].span),
+                span(&tokens, 12, 14),
+            ))),
+            span(&tokens, 4, 14),
+        )),
+        None,
+        span(&tokens, 0, 14),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_logical_precedence() {
+    let (tokens, _) = Scanner::init("true or false and nil;").scan_tokens();
+
+    let expected = vec![Statement::Expression(
+        Expr::Logical(
+            Box::new(Expr::Literal(LiteralValue::Boolean(true), tokens[0].span)),
+            tokens[1].clone(),
+            Box::new(Expr::Logical(
+                Box::new(Expr::Literal(LiteralValue::Boolean(false), tokens[2].span)),
+                tokens[3].clone(),
+                Box::new(Expr::Literal(LiteralValue::Nil, tokens[4].span)),
+                span(&tokens, 2, 4),
+            )),
+            span(&tokens, 0, 4),
+        ),
+        span(&tokens, 0, 5),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_while_statement() {
+    let (tokens, _) = Scanner::init("while (false) print 1;").scan_tokens();
+
+    let expected = vec![Statement::While(
+        Expr::Literal(LiteralValue::Boolean(false), tokens[2].span),
+        Box::new(Statement::Print(
+            Expr::Literal(LiteralValue::Number(1.0), tokens[5].span),
+            span(&tokens, 4, 6),
+        )),
+        span(&tokens, 0, 6),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_for_statement_is_desugared() {
+    let (tokens, _) = Scanner::init("for (var i = 0; i < 3; i = i + 1) print i;").scan_tokens();
+    let for_span = span(&tokens, 0, 19);
+
+    let expected = vec![Statement::Block(
+        vec![
+            Statement::Var(
+                tokens[3].clone(),
+                Some(Expr::Literal(LiteralValue::Number(0.0), tokens[5].span)),
+                span(&tokens, 2, 6),
+            ),
+            Statement::While(
+                Expr::Binary(
+                    Box::new(Expr::Variable(
+                        tokens[7].clone(),
+                        Binding::default(),
+                        tokens[7].span,
+                    )),
+                    tokens[8].clone(),
+                    Box::new(Expr::Literal(LiteralValue::Number(3.0), tokens[9].span)),
+                    span(&tokens, 7, 9),
+                ),
+                Box::new(Statement::Block(
+                    vec![
+                        Statement::Print(
+                            Expr::Variable(tokens[18].clone(), Binding::default(), tokens[18].span),
+                            span(&tokens, 17, 19),
+                        ),
+                        Statement::Expression(
+                            Expr::Assignment(
+                                tokens[11].clone(),
+                                Box::new(Expr::Binary(
+                                    Box::new(Expr::Variable(
+                                        tokens[13].clone(),
+                                        Binding::default(),
+                                        tokens[13].span,
+                                    )),
+                                    tokens[14].clone(),
+                                    Box::new(Expr::Literal(
+                                        LiteralValue::Number(1.0),
+                                        tokens[15].span,
+                                    )),
+                                    span(&tokens, 13, 15),
+                                )),
+                                Binding::default(),
+                                span(&tokens, 11, 15),
+                            ),
+                            span(&tokens, 11, 15),
+                        ),
+                    ],
+                    for_span,
+                )),
+                for_span,
+            ),
+        ],
+        for_span,
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_for_statement_with_empty_clauses() {
+    let (tokens, _) = Scanner::init("for (;;) print 1;").scan_tokens();
+
+    // the missing condition is located at the semicolon where it would be
+    let expected = vec![Statement::While(
+        Expr::Literal(LiteralValue::Boolean(true), tokens[3].span),
+        Box::new(Statement::Print(
+            Expr::Literal(LiteralValue::Number(1.0), tokens[6].span),
+            span(&tokens, 5, 7),
+        )),
+        span(&tokens, 0, 7),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_function_declaration() {
+    let (tokens, _) = Scanner::init("fun add(a, b) { return a + b; }").scan_tokens();
+
+    let expected = vec![Statement::Function(Rc::new(FunctionDeclaration {
+        name: tokens[1].clone(),
+        params: vec![tokens[3].clone(), tokens[5].clone()],
+        body: vec![Statement::Return(
+            tokens[8].clone(),
+            Some(Expr::Binary(
+                Box::new(Expr::Variable(
+                    tokens[9].clone(),
+                    Binding::default(),
+                    tokens[9].span,
+                )),
+                tokens[10].clone(),
+                Box::new(Expr::Variable(
+                    tokens[11].clone(),
+                    Binding::default(),
+                    tokens[11].span,
+                )),
+                span(&tokens, 9, 11),
+            )),
+            span(&tokens, 8, 12),
+        )],
+        span: span(&tokens, 0, 13),
+    }))];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_chained_calls() {
+    let (tokens, _) = Scanner::init("make(1)(2, 3);").scan_tokens();
+
+    let expected = vec![Statement::Expression(
+        Expr::Call(
+            Box::new(Expr::Call(
+                Box::new(Expr::Variable(
+                    tokens[0].clone(),
+                    Binding::default(),
+                    tokens[0].span,
+                )),
+                tokens[3].clone(),
+                vec![Expr::Literal(LiteralValue::Number(1.0), tokens[2].span)],
+                span(&tokens, 0, 3),
+            )),
+            tokens[8].clone(),
+            vec![
+                Expr::Literal(LiteralValue::Number(2.0), tokens[5].span),
+                Expr::Literal(LiteralValue::Number(3.0), tokens[7].span),
+            ],
+            span(&tokens, 0, 8),
+        ),
+        span(&tokens, 0, 9),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_too_many_arguments() {
+    let arguments = vec!["1"; 256].join(", ");
+    let (tokens, _) = Scanner::init(&format!("f({arguments});")).scan_tokens();
+
+    assert_eq!(
+        error_messages(&tokens),
+        vec!["[line 1] Error at '1': Can't have more than 255 arguments."]
+    );
+}
+
+#[test]
+fn test_class_declaration() {
+    let (tokens, _) = Scanner::init("class Point { init(x) { this.x = x; } }").scan_tokens();
+
+    let expected = vec![Statement::Class(
+        tokens[1].clone(),
+        None,
+        vec![Rc::new(FunctionDeclaration {
+            name: tokens[3].clone(),
+            params: vec![tokens[5].clone()],
+            body: vec![Statement::Expression(
+                Expr::Set(
+                    Box::new(Expr::This(
+                        tokens[8].clone(),
+                        Binding::default(),
+                        tokens[8].span,
+                    )),
+                    tokens[10].clone(),
+                    Box::new(Expr::Variable(
+                        tokens[12].clone(),
+                        Binding::default(),
+                        tokens[12].span,
+                    )),
+                    span(&tokens, 8, 12),
+                ),
+                span(&tokens, 8, 13),
+            )],
+            span: span(&tokens, 3, 14),
+        })],
+        span(&tokens, 0, 15),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_property_access_chain() {
+    let (tokens, _) = Scanner::init("a.b().c;").scan_tokens();
+
+    let expected = vec![Statement::Expression(
+        Expr::Get(
+            Box::new(Expr::Call(
+                Box::new(Expr::Get(
+                    Box::new(Expr::Variable(
+                        tokens[0].clone(),
+                        Binding::default(),
+                        tokens[0].span,
+                    )),
+                    tokens[2].clone(),
+                    span(&tokens, 0, 2),
+                )),
+                tokens[4].clone(),
+                vec![],
+                span(&tokens, 0, 4),
+            )),
+            tokens[6].clone(),
+            span(&tokens, 0, 6),
+        ),
+        span(&tokens, 0, 7),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_invalid_assignment_target() {
+    let (tokens, _) = Scanner::init("a + b = c;").scan_tokens();
+
+    assert_eq!(
+        error_messages(&tokens),
+        vec!["[line 1] Error at '=': Invalid assignment target."]
+    );
+}
+
+#[test]
+fn test_subclass_declaration() {
+    let (tokens, _) = Scanner::init("class B < A { m() { super.m(); } }").scan_tokens();
+
+    let expected = vec![Statement::Class(
+        tokens[1].clone(),
+        Some(Expr::Variable(
+            tokens[3].clone(),
+            Binding::default(),
+            tokens[3].span,
+        )),
+        vec![Rc::new(FunctionDeclaration {
+            name: tokens[5].clone(),
+            params: vec![],
+            body: vec![Statement::Expression(
+                Expr::Call(
+                    Box::new(Expr::Super(
+                        tokens[9].clone(),
+                        tokens[11].clone(),
+                        Binding::default(),
+                        span(&tokens, 9, 11),
+                    )),
+                    tokens[13].clone(),
+                    vec![],
+                    span(&tokens, 9, 13),
+                ),
+                span(&tokens, 9, 14),
+            )],
+            span: span(&tokens, 5, 15),
+        })],
+        span(&tokens, 0, 16),
+    )];
+
+    let mut parser = Parser::init(&tokens[..]);
+    assert_eq!(Ok(expected), parser.parse());
+}
+
+#[test]
+fn test_class_inheriting_from_itself() {
+    let (tokens, _) = Scanner::init("class A < A {}").scan_tokens();
+
+    assert_eq!(
+        error_messages(&tokens),
+        vec!["[line 1] Error at 'A': A class can't inherit from itself."]
+    );
+}
+
+#[test]
+fn test_missing_semicolon() {
+    let (tokens, _) = Scanner::init("print 1\nprint 2;").scan_tokens();
+
+    assert_eq!(
+        error_messages(&tokens),
+        vec!["[line 2] Error at 'print': Expect ';' after value."]
+    );
+}
+
+#[test]
+fn test_error_at_end() {
+    let (tokens, _) = Scanner::init("var a = (1 + 2").scan_tokens();
+
+    assert_eq!(
+        error_messages(&tokens),
+        vec!["[line 1] Error at end: Expect ')' after expression."]
+    );
+}
+
+#[test]
+fn test_reports_every_error_after_synchronizing() {
+    let (tokens, _) = Scanner::init(
+        "var = 1;
+        print 2;
+        fun (a) {}
+        {
+            var b = ;
+            print b;
+        }
+        class A { m( }",
+    )
+    .scan_tokens();
+
+    assert_eq!(
+        error_messages(&tokens),
+        vec![
+            "[line 1] Error at '=': Expect variable name.",
+            "[line 3] Error at '(': Expect function name.",
+            "[line 5] Error at ';': Expect expression.",
+            // synchronizing after the bad function skipped the opening brace of the block
+            "[line 7] Error at '}': Expect expression.",
+            "[line 8] Error at '}': Expect parameter name.",
+        ]
+    );
+}
+
+#[test]
+fn test_spans_cover_multiple_lines() {
+    let (tokens, _) = Scanner::init("print\n  (1 +\n  2);").scan_tokens();
+    let Ok(statements) = Parser::init(&tokens).parse() else {
+        panic!("failed to parse");
+    };
+
+    let Statement::Print(expr, _) = &statements[0] else {
+        panic!("expected a print statement");
+    };
+    assert_eq!(statements[0].span(), Span::new(0, 18, 1, 1));
+    assert_eq!(expr.span(), Span::new(8, 17, 2, 3));
 }
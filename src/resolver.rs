@@ -0,0 +1,501 @@
+use crate::expression::{Binding, Expr, LiteralValue, Local, Visitor};
+use crate::interner::intern;
+#[cfg(test)]
+use crate::parser::Parser;
+#[cfg(test)]
+use crate::scanner::Scanner;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::Token;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] Error at '{}': {}",
+            self.token.line, self.token.lexeme, self.message
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// a variable declared in a local scope
+struct Variable {
+    // its index among the scope's variables, in the order they are declared
+    slot: usize,
+    // whether its initializer has finished
+    defined: bool,
+}
+
+// Walks the syntax tree once before it is executed and stores, for every variable
+// reference, how many scopes away its declaration is and at which slot of that scope.
+// Globals are left unresolved.
+pub struct Resolver {
+    // innermost scope last
+    scopes: Vec<HashMap<Rc<str>, Variable>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<ResolveError>,
+}
+
+impl Visitor<()> for Resolver {
+    fn visit_binary_expression(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.visit_expression(left);
+        self.visit_expression(right);
+    }
+
+    fn visit_call_expression(&mut self, callee: &Expr, _paren: &Token, arguments: &[Expr]) {
+        self.visit_expression(callee);
+        for argument in arguments {
+            self.visit_expression(argument);
+        }
+    }
+
+    fn visit_get_expression(&mut self, object: &Expr, _name: &Token) {
+        self.visit_expression(object);
+    }
+
+    fn visit_grouping_expression(&mut self, expr: &Expr) {
+        self.visit_expression(expr);
+    }
+
+    fn visit_literal_expression(&mut self, _value: &LiteralValue) {}
+
+    fn visit_logical_expression(&mut self, left: &Expr, _operator: &Token, right: &Expr) {
+        self.visit_expression(left);
+        self.visit_expression(right);
+    }
+
+    fn visit_set_expression(&mut self, object: &Expr, _name: &Token, value: &Expr) {
+        self.visit_expression(value);
+        self.visit_expression(object);
+    }
+
+    fn visit_super_expression(&mut self, keyword: &Token, _method: &Token, binding: &Binding) {
+        match self.current_class {
+            ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+            ClassType::Class => {
+                self.error(keyword, "Can't use 'super' in a class with no superclass.")
+            }
+            ClassType::Subclass => self.resolve_local(keyword, binding),
+        }
+    }
+
+    fn visit_this_expression(&mut self, keyword: &Token, binding: &Binding) {
+        if self.current_class == ClassType::None {
+            self.error(keyword, "Can't use 'this' outside of a class.");
+            return;
+        }
+
+        self.resolve_local(keyword, binding);
+    }
+
+    fn visit_unary_expression(&mut self, _operator: &Token, expr: &Expr) {
+        self.visit_expression(expr);
+    }
+
+    fn visit_expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(left, operator, right, _) => {
+                self.visit_binary_expression(left, operator, right)
+            }
+            Expr::Call(callee, paren, arguments, _) => {
+                self.visit_call_expression(callee, paren, arguments)
+            }
+            Expr::Get(object, name, _) => self.visit_get_expression(object, name),
+            Expr::Grouping(expr, _) => self.visit_grouping_expression(expr),
+            Expr::Literal(literal_value, _) => self.visit_literal_expression(literal_value),
+            Expr::Logical(left, operator, right, _) => {
+                self.visit_logical_expression(left, operator, right)
+            }
+            Expr::Set(object, name, value, _) => self.visit_set_expression(object, name, value),
+            Expr::Super(keyword, method, binding, _) => {
+                self.visit_super_expression(keyword, method, binding)
+            }
+            Expr::This(keyword, binding, _) => self.visit_this_expression(keyword, binding),
+            Expr::Unary(operator, expr, _) => self.visit_unary_expression(operator, expr),
+            Expr::Variable(token, binding, _) => self.visit_variable_expression(token, binding),
+            Expr::Assignment(token, expr, binding, _) => {
+                self.visit_assignment_expression(token, expr, binding)
+            }
+        }
+    }
+
+    fn visit_variable_expression(&mut self, name_token: &Token, binding: &Binding) {
+        let declared_but_undefined = self
+            .scopes
+            .last()
+            .and_then(|scope| scope.get(&name_token.lexeme))
+            .is_some_and(|variable| !variable.defined);
+        if declared_but_undefined {
+            self.error(
+                name_token,
+                "Can't read local variable in its own initializer.",
+            );
+        }
+
+        self.resolve_local(name_token, binding);
+    }
+
+    fn visit_assignment_expression(&mut self, name_token: &Token, right: &Expr, binding: &Binding) {
+        self.visit_expression(right);
+        self.resolve_local(name_token, binding);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr, _) => self.visit_expression(expr),
+            Statement::Print(expr, _) => self.visit_expression(expr),
+            Statement::Var(token, initializer, _) => {
+                self.visit_var_declaration_statement(token, initializer)
+            }
+            Statement::Block(statements, _) => self.visit_block(statements),
+            Statement::If(condition, then_branch, else_branch, _) => {
+                self.visit_if_statement(condition, then_branch, else_branch)
+            }
+            Statement::While(condition, body, _) => self.visit_while_statement(condition, body),
+            Statement::Function(declaration) => self.visit_function_statement(declaration),
+            Statement::Return(keyword, value, _) => self.visit_return_statement(keyword, value),
+            Statement::Class(name, superclass, methods, _) => {
+                self.visit_class_statement(name, superclass, methods)
+            }
+        }
+    }
+
+    fn visit_var_declaration_statement(&mut self, token: &Token, initializer: &Option<Expr>) {
+        self.declare(token);
+        if let Some(initializer) = initializer {
+            self.visit_expression(initializer);
+        }
+        self.define(token);
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) {
+        self.begin_scope();
+        self.resolve_statements(statements);
+        self.end_scope();
+    }
+
+    fn visit_if_statement(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Statement,
+        else_branch: &Option<Box<Statement>>,
+    ) {
+        self.visit_expression(condition);
+        self.visit_statement(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.visit_statement(else_branch);
+        }
+    }
+
+    fn visit_while_statement(&mut self, condition: &Expr, body: &Statement) {
+        self.visit_expression(condition);
+        self.visit_statement(body);
+    }
+
+    fn visit_function_statement(&mut self, declaration: &Rc<FunctionDeclaration>) {
+        // defined before the body is resolved so that the function can call itself
+        self.declare(&declaration.name);
+        self.define(&declaration.name);
+
+        self.resolve_function(declaration, FunctionType::Function);
+    }
+
+    fn visit_return_statement(&mut self, keyword: &Token, value: &Option<Expr>) {
+        if self.current_function == FunctionType::None {
+            self.error(keyword, "Can't return from top-level code.");
+        }
+
+        if let Some(value) = value {
+            if self.current_function == FunctionType::Initializer {
+                self.error(keyword, "Can't return a value from an initializer.");
+            }
+            self.visit_expression(value);
+        }
+    }
+
+    fn visit_class_statement(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) {
+        let enclosing_class = mem::replace(&mut self.current_class, ClassType::Class);
+
+        self.declare(name);
+        self.define(name);
+
+        // mirrors the scopes the interpreter creates for `super` and `this`
+        if let Some(superclass) = superclass {
+            self.current_class = ClassType::Subclass;
+            self.visit_expression(superclass);
+
+            self.begin_scope();
+            self.define_name("super");
+        }
+
+        self.begin_scope();
+        self.define_name("this");
+
+        for method in methods {
+            let function_type = if &*method.name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, function_type);
+        }
+
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, statements: &[Statement]) -> Result<(), Vec<ResolveError>> {
+        self.resolve_statements(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDeclaration, function_type: FunctionType) {
+        let enclosing_function = mem::replace(&mut self.current_function, function_type);
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_local(&mut self, name: &Token, binding: &Binding) {
+        let local = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                let variable = scope.get(&name.lexeme)?;
+                Some(Local {
+                    depth,
+                    slot: variable.slot,
+                })
+            });
+
+        binding.set(local);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+        let slot = scope.len();
+        scope.insert(
+            name.lexeme.clone(),
+            Variable {
+                slot,
+                defined: false,
+            },
+        );
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    // `this` and `super` are defined without being declared, they get the next slot
+    fn define_name(&mut self, name: &str) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        let slot = scope.len();
+        scope
+            .entry(intern(name))
+            .or_insert(Variable {
+                slot,
+                defined: false,
+            })
+            .defined = true;
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError {
+            token: token.clone(),
+            message: message.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+fn resolve_source(source: &str) -> (Vec<Statement>, Result<(), Vec<ResolveError>>) {
+    let (tokens, _) = Scanner::init(source).scan_tokens();
+    let statements = Parser::init(&tokens).parse().expect("Parsing failed");
+
+    let result = Resolver::new().resolve(&statements);
+    (statements, result)
+}
+
+#[cfg(test)]
+fn error_messages(source: &str) -> Vec<String> {
+    match resolve_source(source).1 {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+    }
+}
+
+#[test]
+fn test_globals_are_left_unresolved() {
+    let (statements, result) = resolve_source("var a = 1; a;");
+
+    assert_eq!(result, Ok(()));
+    let Statement::Expression(Expr::Variable(_, binding, _), _) = &statements[1] else {
+        panic!("Expected a variable expression");
+    };
+    assert_eq!(binding.get(), None);
+}
+
+#[test]
+fn test_local_depths_and_slots() {
+    let (statements, result) = resolve_source("{ var a = 1; var b = 2; { b; } a; }");
+
+    assert_eq!(result, Ok(()));
+    let Statement::Block(outer, _) = &statements[0] else {
+        panic!("Expected a block");
+    };
+    let Statement::Block(inner, _) = &outer[2] else {
+        panic!("Expected a block");
+    };
+    let (
+        Statement::Expression(Expr::Variable(_, inner_depth, _), _),
+        Statement::Expression(Expr::Variable(_, outer_depth, _), _),
+    ) = (&inner[0], &outer[3])
+    else {
+        panic!("Expected variable expressions");
+    };
+    assert_eq!(inner_depth.get(), Some(Local { depth: 1, slot: 1 }));
+    assert_eq!(outer_depth.get(), Some(Local { depth: 0, slot: 0 }));
+}
+
+#[test]
+fn test_read_local_in_own_initializer() {
+    assert_eq!(
+        error_messages("var a = 1; { var a = a; }"),
+        vec!["Can't read local variable in its own initializer."]
+    );
+}
+
+#[test]
+fn test_duplicate_local_declaration() {
+    assert_eq!(
+        error_messages("fun f(a) { var a; } { var b; var b; }"),
+        vec![
+            "Already a variable with this name in this scope.",
+            "Already a variable with this name in this scope."
+        ]
+    );
+    assert_eq!(error_messages("var a; var a;"), Vec::<String>::new());
+}
+
+#[test]
+fn test_top_level_return() {
+    assert_eq!(
+        error_messages("return 1;"),
+        vec!["Can't return from top-level code."]
+    );
+}
+
+#[test]
+fn test_return_value_from_initializer() {
+    assert_eq!(
+        error_messages("class A { init() { return 1; } }"),
+        vec!["Can't return a value from an initializer."]
+    );
+    assert_eq!(
+        error_messages("class A { init() { return; } }"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_this_and_super_outside_of_class() {
+    assert_eq!(
+        error_messages("this; fun f() { super.g(); } class A { m() { super.m(); } }"),
+        vec![
+            "Can't use 'this' outside of a class.",
+            "Can't use 'super' outside of a class.",
+            "Can't use 'super' in a class with no superclass."
+        ]
+    );
+}
@@ -1,54 +1,140 @@
-use crate::token::{Token, TokenType};
+use crate::interner::intern;
+use crate::token::{Span, Token, TokenType};
 #[cfg(test)]
 use pretty_assertions::assert_eq;
 use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Formatter;
 use std::iter::Peekable;
+use std::mem;
+use std::rc::Rc;
 use std::str::Chars;
 
 pub struct Scanner<'a> {
     source: Peekable<Chars<'a>>,
     line: u32,
+    // byte offset and column of the next character
+    offset: usize,
+    column: u32,
+    // where the token being scanned starts
+    token_start: usize,
+    token_line: u32,
+    token_column: u32,
+    // the id the spans are given, see `next_source_id`
+    source_id: u32,
+    errors: Vec<TokenizerError>,
 }
 
-#[derive(Debug)]
-enum TokenizerError {
-    UnterminatedString,
+// `line` is the line the error is reported on, which for unterminated strings and comments
+// is the last line of the source, like in the reference implementation
+#[derive(Debug, PartialEq)]
+pub enum TokenizerError {
+    UnexpectedCharacter {
+        character: char,
+        line: u32,
+        span: Span,
+    },
+    UnterminatedString {
+        line: u32,
+        span: Span,
+    },
+    UnterminatedComment {
+        line: u32,
+        span: Span,
+    },
+    // a number running straight into letters, like `123abc`
+    MalformedNumber {
+        lexeme: String,
+        line: u32,
+        span: Span,
+    },
+}
+
+impl TokenizerError {
+    pub fn line(&self) -> u32 {
+        match self {
+            TokenizerError::UnexpectedCharacter { line, .. }
+            | TokenizerError::UnterminatedString { line, .. }
+            | TokenizerError::UnterminatedComment { line, .. }
+            | TokenizerError::MalformedNumber { line, .. } => *line,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            TokenizerError::UnexpectedCharacter { span, .. }
+            | TokenizerError::UnterminatedString { span, .. }
+            | TokenizerError::UnterminatedComment { span, .. }
+            | TokenizerError::MalformedNumber { span, .. } => *span,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            TokenizerError::UnexpectedCharacter { .. } => "Unexpected character.",
+            TokenizerError::UnterminatedString { .. } => "Unterminated string.",
+            TokenizerError::UnterminatedComment { .. } => "Unterminated comment.",
+            TokenizerError::MalformedNumber { .. } => "Malformed number.",
+        }
+    }
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line(), self.message())
+    }
 }
 
 impl<'a> Scanner<'a> {
     pub fn init(source: &'a str) -> Self {
         Scanner {
-            // enumerate instead?
-            // if we do, we can have index of the current character
             source: source.chars().peekable(),
             line: 1,
+            offset: 0,
+            column: 1,
+            token_start: 0,
+            token_line: 1,
+            token_column: 1,
+            source_id: 0,
+            errors: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    pub fn in_source(mut self, source_id: u32) -> Self {
+        self.source_id = source_id;
+        self
+    }
+
+    // scanning goes on after an error, so that every error in the source is reported
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<TokenizerError>) {
         let mut tokens: Vec<Token> = Vec::new();
 
-        while let Some(c) = self.source.next() {
+        loop {
+            self.start_token();
+            let Some(c) = self.advance() else {
+                break;
+            };
+
             match c {
-                '(' => tokens.push(self.make_token(c.to_string(), TokenType::LeftParen)),
-                ')' => tokens.push(self.make_token(c.to_string(), TokenType::RightParen)),
-                '{' => tokens.push(self.make_token(c.to_string(), TokenType::LeftBrace)),
-                '}' => tokens.push(self.make_token(c.to_string(), TokenType::RightBrace)),
-                ',' => tokens.push(self.make_token(c.to_string(), TokenType::Comma)),
-                '.' => tokens.push(self.make_token(c.to_string(), TokenType::Period)),
-                '-' => tokens.push(self.make_token(c.to_string(), TokenType::Minus)),
-                '+' => tokens.push(self.make_token(c.to_string(), TokenType::Plus)),
-                '*' => tokens.push(self.make_token(c.to_string(), TokenType::Star)),
-                ';' => tokens.push(self.make_token(c.to_string(), TokenType::Semicolon)),
+                '(' => tokens.push(self.make_token("(", TokenType::LeftParen)),
+                ')' => tokens.push(self.make_token(")", TokenType::RightParen)),
+                '{' => tokens.push(self.make_token("{", TokenType::LeftBrace)),
+                '}' => tokens.push(self.make_token("}", TokenType::RightBrace)),
+                ',' => tokens.push(self.make_token(",", TokenType::Comma)),
+                '.' => tokens.push(self.make_token(".", TokenType::Period)),
+                '-' => tokens.push(self.make_token("-", TokenType::Minus)),
+                '+' => tokens.push(self.make_token("+", TokenType::Plus)),
+                '*' => tokens.push(self.make_token("*", TokenType::Star)),
+                ';' => tokens.push(self.make_token(";", TokenType::Semicolon)),
                 '!' => {
                     let mut op = TokenType::Bang;
                     let mut lexeme = c.to_string();
                     if let Some('=') = self.source.peek() {
                         op = TokenType::BangEqual;
                         lexeme.push('=');
-                        self.source.next();
+                        self.advance();
                     }
-                    tokens.push(self.make_token(lexeme, op));
+                    tokens.push(self.make_token(&lexeme, op));
                 }
                 '=' => {
                     let mut op = TokenType::Equal;
@@ -56,9 +142,9 @@ impl<'a> Scanner<'a> {
                     if let Some('=') = self.source.peek() {
                         op = TokenType::EqualEqual;
                         lexeme.push('=');
-                        self.source.next();
+                        self.advance();
                     }
-                    tokens.push(self.make_token(lexeme, op));
+                    tokens.push(self.make_token(&lexeme, op));
                 }
                 '<' => {
                     let mut op = TokenType::Less;
@@ -66,9 +152,9 @@ impl<'a> Scanner<'a> {
                     if let Some('=') = self.source.peek() {
                         op = TokenType::LessEqual;
                         lexeme.push('=');
-                        self.source.next();
+                        self.advance();
                     }
-                    tokens.push(self.make_token(lexeme, op));
+                    tokens.push(self.make_token(&lexeme, op));
                 }
                 '>' => {
                     let mut op = TokenType::Greater;
@@ -76,90 +162,141 @@ impl<'a> Scanner<'a> {
                     if let Some('=') = self.source.peek() {
                         op = TokenType::GreaterEqual;
                         lexeme.push('=');
-                        self.source.next();
+                        self.advance();
                     }
-                    tokens.push(self.make_token(lexeme, op));
+                    tokens.push(self.make_token(&lexeme, op));
                 }
                 '/' => {
                     if let Some('/') = self.source.peek() {
-                        while self.source.peek().map_or(false, |&c| c != '\n') {
-                            self.source.next();
+                        while self.source.peek().is_some_and(|&c| c != '\n') {
+                            self.advance();
                         }
                     } else if let Some('*') = self.source.peek() {
-                        while let Some(c) = self.source.next() {
-                            match c {
-                                '*' => {
-                                    if let Some('/') = self.source.peek() {
-                                        self.source.next();
-                                        break;
-                                    }
-                                }
-                                '\n' => {
-                                    self.line += 1;
-                                }
-                                _ => {}
-                            }
-                        }
+                        self.skip_block_comment();
                     } else {
-                        tokens.push(self.make_token(c.to_string(), TokenType::Slash));
+                        tokens.push(self.make_token("/", TokenType::Slash));
                     }
                 }
                 ' ' | '\r' | '\t' => (),
                 '\n' => {
                     self.line += 1;
                 }
-                '"' => {
-                    tokens.push(self.tokenize_string_literal().unwrap());
-                }
-                '0'..='9' => {
-                    tokens.append(self.tokenize_number(c).as_mut());
-                }
+                '"' => match self.tokenize_string_literal() {
+                    Ok(token) => tokens.push(token),
+                    Err(error) => self.errors.push(error),
+                },
+                '0'..='9' => match self.tokenize_number(c) {
+                    Ok(mut number_tokens) => tokens.append(&mut number_tokens),
+                    Err(error) => self.errors.push(error),
+                },
                 'a'..='z' | 'A'..='Z' | '_' => {
                     tokens.push(self.tokenize_identifier(c));
                 }
                 _ => {
-                    eprintln!("Unexpected character {} at line {}", c, self.line);
+                    self.errors.push(TokenizerError::UnexpectedCharacter {
+                        character: c,
+                        line: self.line,
+                        span: self.token_span(),
+                    });
+                }
+            }
+        }
+
+        self.start_token();
+        tokens.push(self.make_token("", TokenType::Eof));
+
+        (tokens, mem::take(&mut self.errors))
+    }
+
+    // skips the rest of a `/* ... */` comment after its opening slash
+    fn skip_block_comment(&mut self) {
+        self.advance();
+
+        while let Some(c) = self.advance() {
+            match c {
+                '*' => {
+                    if let Some('/') = self.source.peek() {
+                        self.advance();
+                        return;
+                    }
+                }
+                '\n' => {
+                    self.line += 1;
                 }
+                _ => {}
             }
         }
 
-        tokens.push(self.make_token(String::from(""), TokenType::Eof));
+        self.errors.push(TokenizerError::UnterminatedComment {
+            line: self.line,
+            span: self.token_span(),
+        });
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.source.next()?;
+
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn start_token(&mut self) {
+        self.token_start = self.offset;
+        self.token_line = self.line;
+        self.token_column = self.column;
+    }
 
-        tokens
+    fn token_span(&self) -> Span {
+        Span::new(
+            self.token_start,
+            self.offset,
+            self.token_line,
+            self.token_column,
+        )
+        .in_source(self.source_id)
     }
 
-    fn make_token(&self, lexeme: String, token_type: TokenType) -> Token {
+    fn make_token(&self, lexeme: &str, token_type: TokenType) -> Token {
         Token {
             token_type,
-            lexeme,
+            lexeme: intern(lexeme),
             line: self.line,
+            span: self.token_span(),
         }
     }
 
     fn tokenize_string_literal(&mut self) -> Result<Token, TokenizerError> {
         let mut string_literal = String::from("");
 
-        while self.source.peek().map_or(false, |&c| c != '"') {
+        while self.source.peek().is_some_and(|&c| c != '"') {
             if let Some(char) = self.source.peek() {
                 if char == &'\n' {
                     self.line += 1;
                 }
 
                 string_literal.push(*char);
-                self.source.next();
+                self.advance();
             }
         }
 
         if self.source.peek().is_none() {
-            eprintln!("Unterminated string at {}", self.line);
-            Err(TokenizerError::UnterminatedString)
-        } else {
-            self.source.next();
-            Ok(Token {
-                token_type: TokenType::StringLiteral(string_literal.to_string()),
-                lexeme: string_literal,
+            Err(TokenizerError::UnterminatedString {
                 line: self.line,
+                span: self.token_span(),
             })
+        } else {
+            self.advance();
+            let string_literal = intern(&string_literal);
+            Ok(self.make_token(
+                &string_literal,
+                TokenType::StringLiteral(Rc::clone(&string_literal)),
+            ))
         }
     }
 
@@ -169,44 +306,49 @@ impl<'a> Scanner<'a> {
         while let Some(char) = self.source.peek() {
             if char.is_ascii_alphanumeric() || *char == '_' {
                 token.push(*char);
-                self.source.next();
+                self.advance();
             } else {
                 break;
             }
         }
 
+        let token = intern(&token);
         self.make_token(
-            token.clone(),
-            TokenType::make_keyword(token.as_str()).unwrap_or(TokenType::Identifier(token)),
+            &token,
+            TokenType::make_keyword(&token).unwrap_or(TokenType::Identifier(Rc::clone(&token))),
         )
     }
 
-    fn tokenize_number(&mut self, c: char) -> Vec<Token> {
+    fn tokenize_number(&mut self, c: char) -> Result<Vec<Token>, TokenizerError> {
         let mut tokens: VecDeque<Token> = VecDeque::new();
         let current_line = self.line;
         let mut number = c.to_string();
 
         while let Some('0'..='9') = self.source.peek() {
-            match self.source.next() {
+            match self.advance() {
                 Some(c) => number.push(c),
                 None => break,
             }
         }
+        let number_end = self.offset;
+        let number_column = self.column;
         if let Some(c) = self.source.peek() {
             if c == &'.' {
                 // consume the period
                 // since we can't peek twice, we need to consume the period and either
                 // make a token for it (if the next character after the period is not a number)
                 // or add the period as part of the number
-                self.source.next();
+                self.advance();
 
                 if let Some('0'..='9') = self.source.peek() {
                     number.push('.');
                 } else {
                     let token = Token {
-                        lexeme: ".".to_string(),
+                        lexeme: intern("."),
                         line: current_line,
                         token_type: TokenType::Period,
+                        span: Span::new(number_end, self.offset, current_line, number_column)
+                            .in_source(self.source_id),
                     };
                     tokens.push_back(token);
                 }
@@ -214,17 +356,42 @@ impl<'a> Scanner<'a> {
         }
 
         while let Some('0'..='9') = self.source.peek() {
-            match self.source.next() {
+            match self.advance() {
                 Some(c) => number.push(c),
                 None => break,
             }
         }
 
-        tokens.push_front(self.make_token(
-            number.clone(),
-            TokenType::Number(number.parse::<f64>().unwrap()),
-        ));
-        tokens.into()
+        // letters right after the digits are a mistake, not the start of an identifier
+        if tokens.is_empty()
+            && self
+                .source
+                .peek()
+                .is_some_and(|&c| c.is_ascii_alphabetic() || c == '_')
+        {
+            while let Some(&c) = self.source.peek() {
+                if !c.is_ascii_alphanumeric() && c != '_' {
+                    break;
+                }
+                number.push(c);
+                self.advance();
+            }
+
+            return Err(TokenizerError::MalformedNumber {
+                lexeme: number,
+                line: self.line,
+                span: self.token_span(),
+            });
+        }
+
+        let mut number_token =
+            self.make_token(&number, TokenType::Number(number.parse::<f64>().unwrap()));
+        // a period that turned out not to be part of the number is not part of its span either
+        if !tokens.is_empty() {
+            number_token.span.end = number_end;
+        }
+        tokens.push_front(number_token);
+        Ok(tokens.into())
     }
 }
 
@@ -245,134 +412,158 @@ fn test_scan_tokens() {
     let expected_output = vec![
         Token {
             line: 1,
-            lexeme: String::from("<"),
+            lexeme: Rc::from("<"),
             token_type: TokenType::Less,
+            span: Span::new(0, 1, 1, 1),
         },
         Token {
             line: 1,
-            lexeme: String::from(">"),
+            lexeme: Rc::from(">"),
             token_type: TokenType::Greater,
+            span: Span::new(1, 2, 1, 2),
         },
         Token {
             line: 1,
-            lexeme: String::from("("),
+            lexeme: Rc::from("("),
             token_type: TokenType::LeftParen,
+            span: Span::new(2, 3, 1, 3),
         },
         Token {
             line: 1,
-            lexeme: String::from(")"),
+            lexeme: Rc::from(")"),
             token_type: TokenType::RightParen,
+            span: Span::new(3, 4, 1, 4),
         },
         Token {
             line: 1,
-            lexeme: String::from("{"),
+            lexeme: Rc::from("{"),
             token_type: TokenType::LeftBrace,
+            span: Span::new(4, 5, 1, 5),
         },
         Token {
             line: 1,
-            lexeme: String::from("}"),
+            lexeme: Rc::from("}"),
             token_type: TokenType::RightBrace,
+            span: Span::new(8, 9, 1, 9),
         },
         Token {
             line: 1,
-            lexeme: String::from("hello!"),
-            token_type: TokenType::StringLiteral(String::from("hello!")),
+            lexeme: Rc::from("hello!"),
+            token_type: TokenType::StringLiteral(Rc::from("hello!")),
+            span: Span::new(9, 17, 1, 10),
         },
         Token {
             line: 3,
-            lexeme: String::from("!="),
+            lexeme: Rc::from("!="),
             token_type: TokenType::BangEqual,
+            span: Span::new(44, 46, 3, 5),
         },
         Token {
             line: 4,
-            lexeme: String::from("24"),
+            lexeme: Rc::from("24"),
             token_type: TokenType::Number(24.0),
+            span: Span::new(51, 53, 4, 5),
         },
         Token {
             line: 4,
-            lexeme: String::from(")"),
+            lexeme: Rc::from(")"),
             token_type: TokenType::RightParen,
+            span: Span::new(53, 54, 4, 7),
         },
         Token {
             line: 5,
-            lexeme: String::from("102.56"),
+            lexeme: Rc::from("102.56"),
             token_type: TokenType::Number(102.56),
+            span: Span::new(59, 65, 5, 5),
         },
         Token {
             line: 6,
-            lexeme: String::from("var"),
+            lexeme: Rc::from("var"),
             token_type: TokenType::Var,
+            span: Span::new(70, 73, 6, 5),
         },
         Token {
             line: 6,
-            lexeme: String::from("string_val"),
-            token_type: TokenType::Identifier(String::from("string_val")),
+            lexeme: Rc::from("string_val"),
+            token_type: TokenType::Identifier(Rc::from("string_val")),
+            span: Span::new(74, 84, 6, 9),
         },
         Token {
             line: 6,
-            lexeme: String::from("="),
+            lexeme: Rc::from("="),
             token_type: TokenType::Equal,
+            span: Span::new(85, 86, 6, 20),
         },
         Token {
             line: 6,
-            lexeme: String::from("102"),
+            lexeme: Rc::from("102"),
             token_type: TokenType::Number(102.0),
+            span: Span::new(87, 90, 6, 22),
         },
         Token {
             line: 6,
-            lexeme: String::from("."),
+            lexeme: Rc::from("."),
             token_type: TokenType::Period,
+            span: Span::new(90, 91, 6, 25),
         },
         Token {
             line: 6,
-            lexeme: String::from("to_string"),
-            token_type: TokenType::Identifier(String::from("to_string")),
+            lexeme: Rc::from("to_string"),
+            token_type: TokenType::Identifier(Rc::from("to_string")),
+            span: Span::new(91, 100, 6, 26),
         },
         Token {
             line: 6,
-            lexeme: String::from("("),
+            lexeme: Rc::from("("),
             token_type: TokenType::LeftParen,
+            span: Span::new(100, 101, 6, 35),
         },
         Token {
             line: 6,
-            lexeme: String::from(")"),
+            lexeme: Rc::from(")"),
             token_type: TokenType::RightParen,
+            span: Span::new(101, 102, 6, 36),
         },
         Token {
             line: 9,
-            lexeme: String::from("var"),
+            lexeme: Rc::from("var"),
             token_type: TokenType::Var,
+            span: Span::new(174, 177, 9, 28),
         },
         Token {
             line: 9,
-            lexeme: String::from("test"),
-            token_type: TokenType::Identifier(String::from("test")),
+            lexeme: Rc::from("test"),
+            token_type: TokenType::Identifier(Rc::from("test")),
+            span: Span::new(178, 182, 9, 32),
         },
         Token {
             line: 9,
-            lexeme: String::from(";"),
+            lexeme: Rc::from(";"),
             token_type: TokenType::Semicolon,
+            span: Span::new(182, 183, 9, 36),
         },
         Token {
             line: 10,
-            lexeme: String::from("+"),
+            lexeme: Rc::from("+"),
             token_type: TokenType::Plus,
+            span: Span::new(188, 189, 10, 5),
         },
         Token {
             line: 11,
-            lexeme: String::from(""),
+            lexeme: Rc::from(""),
             token_type: TokenType::Eof,
+            span: Span::new(194, 194, 11, 5),
         },
     ];
 
     let mut scanner = Scanner::init(input);
-    let actual_output = scanner.scan_tokens();
+    let (actual_output, errors) = scanner.scan_tokens();
 
-    assert_eq!(expected_output, *actual_output);
+    assert_eq!(expected_output, actual_output);
+    assert_eq!(errors, vec![]);
 }
 
 #[test]
-#[should_panic(expected = "UnterminatedString")]
 fn test_scan_tokens_with_unterminated_string() {
     let input = "<>(){   }\"hello!\
     \
@@ -381,5 +572,73 @@ fn test_scan_tokens_with_unterminated_string() {
     23";
 
     let mut scanner = Scanner::init(input);
-    scanner.scan_tokens();
+    let (tokens, errors) = scanner.scan_tokens();
+
+    assert_eq!(tokens.len(), 7);
+    assert_eq!(
+        errors,
+        vec![TokenizerError::UnterminatedString {
+            line: 2,
+            span: Span::new(9, input.len(), 1, 10),
+        }]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        "[line 2] Error: Unterminated string."
+    );
+}
+
+#[test]
+fn test_scanning_goes_on_after_errors() {
+    let input = "a @ 12ab;\n/* never closed";
+
+    let (tokens, errors) = Scanner::init(input).scan_tokens();
+    let token_types: Vec<TokenType> = tokens.into_iter().map(|token| token.token_type).collect();
+
+    assert_eq!(
+        token_types,
+        vec![
+            TokenType::Identifier(Rc::from("a")),
+            TokenType::Semicolon,
+            TokenType::Eof
+        ]
+    );
+    assert_eq!(
+        errors,
+        vec![
+            TokenizerError::UnexpectedCharacter {
+                character: '@',
+                line: 1,
+                span: Span::new(2, 3, 1, 3),
+            },
+            TokenizerError::MalformedNumber {
+                lexeme: String::from("12ab"),
+                line: 1,
+                span: Span::new(4, 8, 1, 5),
+            },
+            TokenizerError::UnterminatedComment {
+                line: 2,
+                span: Span::new(10, 25, 2, 1),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_spans_use_byte_offsets_and_character_columns() {
+    let (tokens, _) = Scanner::init("\"h\u{e9}llo\" +\n  1.foo").scan_tokens();
+    let spans: Vec<Span> = tokens.iter().map(|token| token.span).collect();
+
+    assert_eq!(
+        spans,
+        vec![
+            // the accented character takes two bytes but a single column
+            Span::new(0, 8, 1, 1),
+            Span::new(9, 10, 1, 9),
+            Span::new(13, 14, 2, 3),
+            Span::new(14, 15, 2, 4),
+            Span::new(15, 18, 2, 5),
+            Span::new(18, 18, 2, 8),
+        ]
+    );
 }
@@ -1,10 +1,43 @@
 use crate::expression::Expr;
-use crate::token::Token;
+use crate::token::{Span, Token};
+use std::rc::Rc;
 
+#[derive(Debug, PartialEq)]
+pub struct FunctionDeclaration {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+// every variant ends with the span of the source it was parsed from
 #[derive(Debug, PartialEq)]
 pub enum Statement {
-    Expression(Expr),
-    Print(Expr),
-    Block(Vec<Statement>),
-    Var(Token, Option<Expr>),
+    Expression(Expr, Span),
+    // the declaration is shared with the function values created from it and holds the span
+    Function(Rc<FunctionDeclaration>),
+    Print(Expr, Span),
+    Return(Token, Option<Expr>, Span),
+    Block(Vec<Statement>, Span),
+    // name, superclass (always an `Expr::Variable`) and methods
+    Class(Token, Option<Expr>, Vec<Rc<FunctionDeclaration>>, Span),
+    If(Expr, Box<Statement>, Option<Box<Statement>>, Span),
+    Var(Token, Option<Expr>, Span),
+    While(Expr, Box<Statement>, Span),
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Function(declaration) => declaration.span,
+            Statement::Expression(.., span)
+            | Statement::Print(.., span)
+            | Statement::Return(.., span)
+            | Statement::Block(.., span)
+            | Statement::Class(.., span)
+            | Statement::If(.., span)
+            | Statement::Var(.., span)
+            | Statement::While(.., span) => *span,
+        }
+    }
 }
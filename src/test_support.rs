@@ -0,0 +1,29 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+// a sink a test can still read after handing it to an interpreter or the machine
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("Output is not UTF-8")
+    }
+
+    // the contents, leaving the buffer empty
+    pub fn take(&self) -> String {
+        String::from_utf8(self.0.take()).expect("Output is not UTF-8")
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,8 +1,58 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// a range of the source code
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    // byte offsets into the source, `end` is exclusive
+    pub start: usize,
+    pub end: usize,
+    // line and column (both starting at 1) of the first character
+    pub line: u32,
+    pub column: u32,
+    // the source the span is in, see `next_source_id`
+    pub source: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: u32, column: u32) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+            source: 0,
+        }
+    }
+
+    pub fn in_source(self, source: u32) -> Span {
+        Span { source, ..self }
+    }
+
+    // the span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: self.end.max(other.end),
+            ..self
+        }
+    }
+}
+
+// every run of source code gets an id of its own, so that the spans of a function declared
+// by an earlier run are not shown against the source of a later one; 0 is no source at all
+pub fn next_source_id() -> u32 {
+    static NEXT: AtomicU32 = AtomicU32::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
-    pub lexeme: String,
+    // interned, see `interner`
+    pub lexeme: Rc<str>,
     pub token_type: TokenType,
+    // the line the token ends on, which is what errors are reported with
     pub line: u32,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -26,8 +76,8 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
-    Identifier(String),
-    StringLiteral(String),
+    Identifier(Rc<str>),
+    StringLiteral(Rc<str>),
     Number(f64),
     And,
     Class,
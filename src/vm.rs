@@ -0,0 +1,736 @@
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+use crate::compiler::Compiler;
+use crate::error::Error;
+use crate::interner::intern;
+use crate::interpreter::RuntimeError;
+use crate::native;
+use crate::object::{BoundMethod, Class, Closure, Instance, Native, Upvalue, Value};
+use crate::optimizer;
+use crate::statement::Statement;
+#[cfg(test)]
+use crate::test_support::SharedBuffer;
+use crate::token::next_source_id;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+// deep enough for any sensible recursion, and it stops runaway recursion with a Lox error
+pub(crate) const FRAMES_MAX: usize = 1024;
+
+// a running function, its locals start at `slots` on the stack
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    slots: usize,
+}
+
+// The bytecode backend: runs compiled scripts on a value stack instead of walking the syntax
+// tree. Globals survive from one script to the next, like with the `Interpreter`.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    // the upvalues still pointing into the stack
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // where `print` writes to
+    output: Box<dyn Write>,
+    // where errors are reported to
+    errors: Box<dyn Write>,
+    // whether `run` passes the statements through the optimizer before compiling them
+    optimize: bool,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    // prints to stdout and reports errors to stderr
+    pub fn new() -> Self {
+        Self::with_output(io::stdout(), io::stderr())
+    }
+
+    pub fn with_output(output: impl Write + 'static, errors: impl Write + 'static) -> Self {
+        let mut globals = HashMap::new();
+        let clock = Native {
+            name: "clock",
+            arity: 0,
+            function: |_| Value::Number(native::seconds_since_epoch()),
+        };
+        globals.insert(intern(clock.name), Value::Native(Rc::new(clock)));
+
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            output: Box::new(output),
+            errors: Box::new(errors),
+            optimize: true,
+        }
+    }
+
+    // the optimizer is on by default, turning it off compiles the statements just as parsed
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    // compiles and runs `source`, like `Interpreter::run` does
+    pub fn run(&mut self, source: &str) -> Result<Option<Value>, Error> {
+        let source_id = next_source_id();
+        let mut statements = crate::parse_source(source, source_id)?;
+        // decided before optimizing, which can turn other statements into expressions
+        let gives_value = matches!(statements.last(), Some(Statement::Expression(..)));
+        if self.optimize {
+            statements = optimizer::optimize(statements);
+        }
+        let script = Compiler::new()
+            .compile(&statements)
+            .map_err(Error::Compile)?;
+
+        let value = self
+            .execute(script)
+            .map_err(|error| Error::Runtime(error.within(source_id)))?;
+
+        // a script that ends in an expression statement returns its value in any case
+        Ok(gives_value.then_some(value))
+    }
+
+    // writes the diagnostics for `error` to the error sink, `source` must be what was run
+    pub fn report(
+        &mut self,
+        error: &Error,
+        file_name: &str,
+        source: &str,
+        color: bool,
+    ) -> io::Result<()> {
+        error.report(&mut self.errors, file_name, source, color)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).cloned()
+    }
+
+    // runs a compiled script and gives back what it returned; what was left on the stack by
+    // a runtime error in the previous script is cleared first
+    pub fn execute(&mut self, script: Rc<Function>) -> Result<Value, RuntimeError> {
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+
+        let closure = Rc::new(Closure {
+            function: script,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+
+        self.call(closure, 0)?;
+        self.interpret()
+    }
+
+    fn interpret(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let byte = self.read_byte();
+            let Some(op) = OpCode::from_byte(byte) else {
+                return Err(self.error(&format!("Unknown instruction {byte}.")));
+            };
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.read_constant() {
+                        Constant::Number(number) => Value::Number(*number),
+                        Constant::String(string) => Value::String(Rc::clone(string)),
+                        Constant::Function(_) => {
+                            return Err(self.error("Functions are only loaded by closures."))
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(self.undefined_variable(&name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    if !self.globals.contains_key(&name) {
+                        return Err(self.undefined_variable(&name));
+                    }
+                    self.globals.insert(name, self.peek(0).clone());
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = self.peek(0).clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let Value::Instance(instance) = self.peek(0).clone() else {
+                        return Err(self.error("Only instances have properties."));
+                    };
+
+                    // fields shadow methods
+                    let field = instance.fields.borrow().get(&name).cloned();
+                    let value = match field {
+                        Some(value) => value,
+                        None => self.bind_method(&instance.class, &name, self.peek(0).clone())?,
+                    };
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    let Value::Instance(instance) = self.pop() else {
+                        return Err(self.error("Only instances have fields."));
+                    };
+
+                    instance.fields.borrow_mut().insert(name, value.clone());
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    let Value::Class(superclass) = self.pop() else {
+                        return Err(self.error("Invalid use of 'super'."));
+                    };
+                    let receiver = self.pop();
+
+                    let method = self.bind_method(&superclass, &name, receiver)?;
+                    self.stack.push(method);
+                }
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left == right));
+                }
+                OpCode::Greater => self.compare(|left, right| left > right)?,
+                OpCode::GreaterEqual => self.compare(|left, right| left >= right)?,
+                OpCode::Less => self.compare(|left, right| left < right)?,
+                OpCode::LessEqual => self.compare(|left, right| left <= right)?,
+                OpCode::Add => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = match (left, right) {
+                        (Value::Number(left), Value::Number(right)) => Value::Number(left + right),
+                        (Value::String(left), Value::String(right)) => {
+                            Value::String(Rc::from(format!("{left}{right}")))
+                        }
+                        _ => return Err(self.error("Operands must be two numbers or two strings.")),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Subtract => self.arithmetic(|left, right| left - right)?,
+                OpCode::Multiply => self.arithmetic(|left, right| left * right)?,
+                OpCode::Divide => self.arithmetic(|left, right| left / right)?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => {
+                    let Value::Number(number) = self.pop() else {
+                        return Err(self.error("Operand must be a number."));
+                    };
+                    self.stack.push(Value::Number(-number));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    if let Err(err) = writeln!(self.output, "{value}") {
+                        return Err(self.error(&format!("Could not write output: {err}.")));
+                    }
+                }
+                OpCode::Jump => {
+                    let distance = self.read_short();
+                    self.frame_mut().ip += distance;
+                }
+                OpCode::JumpIfFalse => {
+                    let distance = self.read_short();
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += distance;
+                    }
+                }
+                OpCode::Loop => {
+                    let distance = self.read_short();
+                    self.frame_mut().ip -= distance;
+                }
+                OpCode::Call => {
+                    let argument_count = self.read_byte() as usize;
+                    self.call_value(self.peek(argument_count).clone(), argument_count)?;
+                }
+                OpCode::Closure => {
+                    let Constant::Function(function) = self.read_constant() else {
+                        return Err(self.error("Closures can only be created for functions."));
+                    };
+                    let function = Rc::clone(function);
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = Closure { function, upvalues };
+                    self.stack.push(Value::Closure(Rc::new(closure)));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("A function is running");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    self.stack.push(Value::Class(Rc::new(Class::new(name))));
+                }
+                OpCode::Inherit => {
+                    let Value::Class(superclass) = self.peek(1) else {
+                        return Err(self.error("Superclass must be a class."));
+                    };
+                    let Value::Class(subclass) = self.peek(0) else {
+                        return Err(self.error("Only classes can inherit."));
+                    };
+
+                    let inherited = superclass.methods.borrow().clone();
+                    subclass.methods.borrow_mut().extend(inherited);
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_name();
+                    let Value::Closure(method) = self.pop() else {
+                        return Err(self.error("Methods have to be functions."));
+                    };
+                    let Value::Class(class) = self.peek(0) else {
+                        return Err(self.error("Methods can only be added to classes."));
+                    };
+
+                    class.methods.borrow_mut().insert(name, method);
+                }
+            }
+        }
+    }
+
+    // the callee sits on the stack below its arguments
+    fn call_value(&mut self, callee: Value, argument_count: usize) -> Result<(), RuntimeError> {
+        let callee_slot = self.stack.len() - argument_count - 1;
+
+        match callee {
+            Value::Closure(closure) => self.call(closure, argument_count),
+            Value::Native(native) => {
+                check_arity(native.arity, argument_count)
+                    .map_err(|message| self.error(&message))?;
+
+                let result = (native.function)(&self.stack[callee_slot + 1..]);
+                self.stack.truncate(callee_slot);
+                self.stack.push(result);
+                Ok(())
+            }
+            Value::Class(class) => {
+                let instance = Instance::new(Rc::clone(&class));
+                self.stack[callee_slot] = Value::Instance(Rc::new(instance));
+
+                let initializer = class.methods.borrow().get("init").cloned();
+                match initializer {
+                    Some(initializer) => self.call(initializer, argument_count),
+                    None => check_arity(0, argument_count).map_err(|message| self.error(&message)),
+                }
+            }
+            Value::BoundMethod(bound) => {
+                self.stack[callee_slot] = bound.receiver.clone();
+                self.call(Rc::clone(&bound.method), argument_count)
+            }
+            _ => Err(self.error("Can only call functions and classes.")),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, argument_count: usize) -> Result<(), RuntimeError> {
+        check_arity(closure.function.arity, argument_count)
+            .map_err(|message| self.error(&message))?;
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error("Stack overflow."));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - argument_count - 1,
+        });
+        Ok(())
+    }
+
+    fn bind_method(
+        &self,
+        class: &Class,
+        name: &str,
+        receiver: Value,
+    ) -> Result<Value, RuntimeError> {
+        match class.methods.borrow().get(name) {
+            Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                receiver,
+                method: Rc::clone(method),
+            }))),
+            None => Err(self.error(&format!("Undefined property '{name}'."))),
+        }
+    }
+
+    // closures created for the same variable share its upvalue
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // moves the variables from `first_slot` up off the stack and into their upvalues
+    fn close_upvalues(&mut self, first_slot: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= first_slot => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn arithmetic(&mut self, operation: fn(f64, f64) -> f64) -> Result<(), RuntimeError> {
+        let (left, right) = self.number_operands()?;
+        self.stack.push(Value::Number(operation(left, right)));
+        Ok(())
+    }
+
+    fn compare(&mut self, comparison: fn(f64, f64) -> bool) -> Result<(), RuntimeError> {
+        let (left, right) = self.number_operands()?;
+        self.stack.push(Value::Boolean(comparison(left, right)));
+        Ok(())
+    }
+
+    fn number_operands(&mut self) -> Result<(f64, f64), RuntimeError> {
+        match (self.pop(), self.pop()) {
+            (Value::Number(right), Value::Number(left)) => Ok((left, right)),
+            _ => Err(self.error("Operands must be numbers.")),
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("A function is running")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("A function is running")
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.frame().closure.function.chunk
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_short(&mut self) -> usize {
+        let frame = self.frame_mut();
+        let short = frame.closure.function.chunk.read_short(frame.ip);
+        frame.ip += 2;
+        short
+    }
+
+    fn read_constant(&mut self) -> &Constant {
+        let index = self.read_short();
+        &self.chunk().constants[index]
+    }
+
+    // names are stored as string constants
+    fn read_name(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::String(name) => Rc::clone(name),
+            constant => panic!("{constant:?} is not a name"),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("The stack is not empty")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn undefined_variable(&self, name: &str) -> RuntimeError {
+        self.error(&format!("Undefined variable '{name}'."))
+    }
+
+    // an error at the source of the instruction being run
+    fn error(&self, message: &str) -> RuntimeError {
+        let frame = self.frame();
+        let span = frame
+            .closure
+            .function
+            .chunk
+            .spans
+            .get(frame.ip.saturating_sub(1))
+            .copied()
+            .unwrap_or_default();
+
+        RuntimeError::at(span, message)
+    }
+}
+
+fn check_arity(arity: usize, argument_count: usize) -> Result<(), String> {
+    if arity == argument_count {
+        Ok(())
+    } else {
+        Err(format!(
+            "Expected {arity} arguments but got {argument_count}."
+        ))
+    }
+}
+
+#[cfg(test)]
+fn run_source(source: &str) -> (String, Option<String>) {
+    let output = SharedBuffer::default();
+    let mut vm = Vm::with_output(output.clone(), io::sink());
+    let error = vm.run(source).err().map(|error| error.to_string());
+
+    (output.contents(), error)
+}
+
+#[cfg(test)]
+fn output(source: &str) -> String {
+    let (output, error) = run_source(source);
+    assert_eq!(error, None);
+    output
+}
+
+#[cfg(test)]
+fn runtime_error(source: &str) -> String {
+    run_source(source).1.expect("Running succeeded")
+}
+
+#[test]
+fn test_arithmetic_and_strings() {
+    assert_eq!(
+        output("print 1 + 2 * 3; print (1 + 2) * 3; print -4 / 2; print \"a\" + \"b\";"),
+        "7\n9\n-2\nab\n"
+    );
+}
+
+#[test]
+fn test_comparison_and_equality() {
+    assert_eq!(
+        output(
+            "print 1 < 2; print 2 <= 1; print 1 == 1; print \"a\" != \"a\"; print nil == false;
+            var nan = 0 / 0; print nan == nan; print nan >= nan;"
+        ),
+        "true\nfalse\ntrue\nfalse\nfalse\nfalse\nfalse\n"
+    );
+}
+
+#[test]
+fn test_locals_and_globals() {
+    assert_eq!(
+        output(
+            "var a = \"global\";
+            {
+                var a = \"outer\";
+                {
+                    var a = \"inner\";
+                    print a;
+                }
+                print a;
+                a = \"assigned\";
+                print a;
+            }
+            print a;"
+        ),
+        "inner\nouter\nassigned\nglobal\n"
+    );
+}
+
+#[test]
+fn test_control_flow() {
+    assert_eq!(
+        output(
+            "for (var i = 0; i < 3; i = i + 1) { if (i == 1) print \"one\"; else print i; }
+            var n = 0;
+            while (n < 2) n = n + 1;
+            print n;
+            print nil or \"default\";
+            print false and 1;"
+        ),
+        "0\none\n2\n2\ndefault\nfalse\n"
+    );
+}
+
+#[test]
+fn test_closures() {
+    assert_eq!(
+        output(
+            "fun makeCounter() {
+                var count = 0;
+                fun counter() {
+                    count = count + 1;
+                    return count;
+                }
+                return counter;
+            }
+            var first = makeCounter();
+            var second = makeCounter();
+            print first(); print first(); print second();
+
+            var closures;
+            {
+                var shared = \"before\";
+                fun show() { print shared; }
+                closures = show;
+                shared = \"after\";
+            }
+            closures();"
+        ),
+        "1\n2\n1\nafter\n"
+    );
+}
+
+#[test]
+fn test_recursion() {
+    assert_eq!(
+        output(
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            print fib(15);
+            {
+                fun countdown(n) { if (n > 0) { print n; countdown(n - 1); } }
+                countdown(2);
+            }"
+        ),
+        "610\n2\n1\n"
+    );
+}
+
+#[test]
+fn test_classes_and_inheritance() {
+    assert_eq!(
+        output(
+            "class Animal {
+                init(name) { this.name = name; }
+                speak() { return this.name + \" makes a sound\"; }
+            }
+            class Dog < Animal {
+                speak() { return super.speak() + \", woof\"; }
+            }
+            var dog = Dog(\"Rex\");
+            print dog.speak();
+            var speak = dog.speak;
+            dog.name = \"Max\";
+            print speak();
+            print dog.init(\"Bo\") == dog;
+            print Dog; print dog; print speak; print clock;"
+        ),
+        "Rex makes a sound, woof\nMax makes a sound, woof\ntrue\nDog\nDog instance\n<fn speak>\n<native fn>\n"
+    );
+}
+
+#[test]
+fn test_runtime_errors() {
+    assert_eq!(
+        runtime_error("print 1;\nprint -\"a\";"),
+        "[line 2] Error: Operand must be a number."
+    );
+    assert_eq!(
+        runtime_error("print 1 + nil;"),
+        "[line 1] Error: Operands must be two numbers or two strings."
+    );
+    assert_eq!(
+        runtime_error("print missing;"),
+        "[line 1] Error: Undefined variable 'missing'."
+    );
+    assert_eq!(
+        runtime_error("fun f(a) {}\nf(\n1, 2);"),
+        "[line 3] Error: Expected 1 arguments but got 2."
+    );
+    assert_eq!(
+        runtime_error("class A {}\nA().x;"),
+        "[line 2] Error: Undefined property 'x'."
+    );
+    assert_eq!(
+        runtime_error("var NotAClass = 1;\nclass A < NotAClass {}"),
+        "[line 2] Error: Superclass must be a class."
+    );
+    assert_eq!(
+        runtime_error("fun f() { f(); }\nf();"),
+        "[line 1] Error: Stack overflow."
+    );
+}
+
+#[test]
+fn test_machine_is_usable_after_runtime_error() {
+    let mut vm = Vm::with_output(io::sink(), io::sink());
+
+    assert!(vm.run("var a = 1; { var b = 2; b(); }").is_err());
+    assert_eq!(vm.run("a + 1;"), Ok(Some(Value::Number(2.0))));
+    assert_eq!(vm.get_global("a"), Some(Value::Number(1.0)));
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::{env, fs};
+
+fn rslox(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rslox"))
+        .args(arguments)
+        .output()
+        .expect("Failed to run rslox")
+}
+
+fn corpus_script(name: &str) -> String {
+    format!("{}/tests/lox/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+// a `.loxc` file of its own for each test, they run in parallel
+fn compiled_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("rslox-{}-{name}.loxc", std::process::id()))
+}
+
+// a compiled script prints what the script itself prints on the vm, and fails the same way
+fn assert_runs_like_the_source(name: &str, file_name: &str) {
+    let script = corpus_script(name);
+    let compiled = compiled_path(file_name);
+    let compiled = compiled.to_str().unwrap();
+
+    let compile = rslox(&["compile", &script, "-o", compiled]);
+    assert!(
+        compile.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compile.stderr)
+    );
+
+    let from_source = rslox(&["--backend=vm", "--color=never", &script]);
+    let from_compiled = rslox(&["--color=never", compiled]);
+    fs::remove_file(compiled).unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&from_compiled.stdout),
+        String::from_utf8_lossy(&from_source.stdout)
+    );
+    assert_eq!(from_compiled.status.code(), from_source.status.code());
+}
+
+#[test]
+fn test_compiled_scripts_run_like_their_source() {
+    assert_runs_like_the_source("functions/closures.lox", "closures");
+    assert_runs_like_the_source("inheritance/super.lox", "super");
+    assert_runs_like_the_source("control_flow/for.lox", "for");
+}
+
+#[test]
+fn test_compiled_scripts_report_runtime_errors_by_line() {
+    let compiled = compiled_path("runtime-error");
+    fs::write(
+        &compiled,
+        rslox::loxc::serialize(&rslox::compile("print 1;\nprint -\"a\";", true).unwrap()),
+    )
+    .unwrap();
+
+    let output = rslox(&["--color=never", compiled.to_str().unwrap()]);
+    fs::remove_file(&compiled).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 2] Error: Operand must be a number.\n"
+    );
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn test_damaged_files_are_not_run() {
+    let compiled = compiled_path("damaged");
+    let mut bytes = rslox::loxc::serialize(&rslox::compile("print 1;", true).unwrap());
+    *bytes.last_mut().unwrap() ^= 1;
+    fs::write(&compiled, bytes).unwrap();
+
+    let output = rslox(&[compiled.to_str().unwrap()]);
+    fs::remove_file(&compiled).unwrap();
+
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(65));
+}
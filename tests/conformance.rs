@@ -0,0 +1,33 @@
+use std::process::Command;
+
+// runs the bundled corpus through `rslox test`, which prints what went wrong in failing scripts
+fn run_corpus(flags: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_rslox"))
+        .args(flags)
+        .args(["test", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/lox")])
+        .output()
+        .expect("Failed to run rslox");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+#[test]
+fn test_conformance_corpus() {
+    run_corpus(&["--backend=tree"]);
+}
+
+#[test]
+fn test_conformance_corpus_on_the_vm() {
+    run_corpus(&["--backend=vm"]);
+}
+
+// the corpus expects the same output either way, so the optimizer changes nothing it checks
+#[test]
+fn test_conformance_corpus_without_optimizations() {
+    run_corpus(&["--backend=tree", "--no-opt"]);
+    run_corpus(&["--backend=vm", "--no-opt"]);
+}
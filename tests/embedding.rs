@@ -0,0 +1,126 @@
+use rslox::{native, Error, Interpreter, RuntimeError, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn test_run_returns_the_value_of_a_final_expression() {
+    let mut interpreter = Interpreter::new();
+
+    assert_eq!(
+        interpreter.run("var a = 1; a + 2;"),
+        Ok(Some(Value::Number(3.0)))
+    );
+    assert_eq!(interpreter.run("var b = a;"), Ok(None));
+}
+
+#[test]
+fn test_globals_are_shared_with_the_host() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("width", 3.0);
+    interpreter.set_global("name", "box");
+
+    interpreter
+        .run("var area = width * width; var label = name + \"!\";")
+        .unwrap();
+
+    assert_eq!(interpreter.get_global("area"), Some(Value::Number(9.0)));
+    assert_eq!(
+        String::try_from(interpreter.get_global("label").unwrap()),
+        Ok("box!".to_string())
+    );
+    assert_eq!(f64::try_from(Value::Nil), Err(Value::Nil));
+    assert_eq!(interpreter.get_global("missing"), None);
+}
+
+#[test]
+fn test_errors_are_returned_by_kind() {
+    let mut interpreter = Interpreter::new();
+
+    let error = interpreter.run("print 1 +;").unwrap_err();
+    assert!(matches!(error, Error::Syntax(_, _)));
+    assert_eq!(
+        error.to_string(),
+        "[line 1] Error at ';': Expect expression."
+    );
+
+    let error = interpreter.run("{ var a = a; }").unwrap_err();
+    assert!(matches!(error, Error::Resolve(_)));
+
+    let error = interpreter.run("var x = 1;\n-\"x\";").unwrap_err();
+    assert!(error.is_runtime());
+    assert_eq!(
+        error.to_string(),
+        "[line 2] Error: Operand must be a number."
+    );
+
+    // whatever ran before the error stays done
+    assert_eq!(interpreter.get_global("x"), Some(Value::Number(1.0)));
+}
+
+#[test]
+fn test_host_closures_are_callable_from_scripts() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+
+    let messages = Rc::clone(&log);
+    interpreter.define_native("log", 2, move |_, arguments| {
+        let level = native::string(arguments, 0)?;
+        let count = native::number(arguments, 1)?;
+        messages.borrow_mut().push(format!("{level}: {count}"));
+        Ok(Value::Nil)
+    });
+    interpreter.define_native("fail", 0, |_, _| {
+        Err(RuntimeError::native("Something went wrong."))
+    });
+
+    interpreter
+        .run("for (var i = 1; i <= 2; i = i + 1) log(\"info\", i);")
+        .unwrap();
+    assert_eq!(*log.borrow(), vec!["info: 1", "info: 2"]);
+
+    assert_eq!(
+        interpreter
+            .run("log(\"info\", nil);")
+            .unwrap_err()
+            .to_string(),
+        "[line 1] Error: Argument 2 must be a number."
+    );
+    assert_eq!(
+        interpreter.run("\n\nfail();").unwrap_err().to_string(),
+        "[line 3] Error: Something went wrong."
+    );
+}
+
+#[test]
+fn test_natives_can_use_the_interpreter() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_native("lookup", 1, |interpreter, arguments| {
+        let name = native::string(arguments, 0)?;
+        Ok(interpreter.get_global(name).unwrap_or(Value::Nil))
+    });
+
+    assert_eq!(
+        interpreter.run("var answer = 42; lookup(\"answer\");"),
+        Ok(Some(Value::Number(42.0)))
+    );
+}
+
+#[test]
+fn test_natives_can_run_more_source_from_inside_a_function() {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_native("eval", 1, |interpreter, arguments| {
+        let source = native::string(arguments, 0)?.to_string();
+        interpreter
+            .run(&source)
+            .map(|value| value.unwrap_or(Value::Nil))
+            .map_err(|error| RuntimeError::native(&error.to_string()))
+    });
+
+    // the `var` in the nested run is a global, not a local of `g`
+    interpreter
+        .run("fun g() { var a = 10; eval(\"var z = 3;\"); return a; } var result = g();")
+        .unwrap();
+
+    assert_eq!(interpreter.get_global("z"), Some(Value::Number(3.0)));
+    assert_eq!(interpreter.get_global("result"), Some(Value::Number(10.0)));
+}